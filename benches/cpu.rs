@@ -0,0 +1,66 @@
+// instructions per second for the CPU core on its own, no PPU or APU in the way.
+// run with `cargo bench`
+use std::time::{Duration, Instant};
+
+use rustendo::{cpu::CPU, flat_ram::FlatRam, mem::Mem, variant::Variant};
+
+// NTSC NES CPU clock
+const REAL_TIME_HZ: f64 = 1_789_773.0;
+
+fn main() {
+    // a loop over the usual suspects: indexed loads and stores, read-modify-write,
+    // indirect addressing, branches, the stack and jumps
+    let program = [
+        0xa2, 0x00, // 0600: LDX #$00
+        0xbd, 0x00, 0x03, // 0602: LDA $0300,X
+        0x69, 0x01, // 0605: ADC #$01
+        0x9d, 0x00, 0x03, // 0607: STA $0300,X
+        0xe6, 0x10, // 060a: INC $10
+        0xa4, 0x10, // 060c: LDY $10
+        0xb1, 0x20, // 060e: LDA ($20),Y
+        0x0a, // 0610: ASL A
+        0xe8, // 0611: INX
+        0xd0, 0xee, // 0612: BNE $0602
+        0x20, 0x1a, 0x06, // 0614: JSR $061a
+        0x4c, 0x00, 0x06, // 0617: JMP $0600
+        0x48, // 061a: PHA
+        0x68, // 061b: PLA
+        0x60, // 061c: RTS
+    ];
+
+    for variant in [Variant::Ricoh2A03, Variant::Wdc65C02] {
+        bench(variant, &program);
+    }
+}
+
+fn bench(variant: Variant, program: &[u8]) {
+    let mut cpu = CPU::new(FlatRam::new());
+    cpu.variant = variant;
+    cpu.load(program);
+    cpu.reset();
+    cpu.mem_write_u16(0x20, 0x0400);
+
+    // warm up the caches before timing anything
+    for _ in 0..100_000 {
+        cpu.step().unwrap();
+    }
+
+    let start_cycles = cpu.cycles;
+    let start = Instant::now();
+    let mut instructions = 0u64;
+    while start.elapsed() < Duration::from_secs(2) {
+        for _ in 0..10_000 {
+            cpu.step().unwrap();
+        }
+        instructions += 10_000;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let cycles = (cpu.cycles - start_cycles) as f64;
+
+    println!(
+        "{:?}: {:.1}M instructions/s, {:.0}x real time",
+        variant,
+        instructions as f64 / elapsed / 1e6,
+        cycles / elapsed / REAL_TIME_HZ,
+    );
+}
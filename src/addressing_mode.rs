@@ -9,5 +9,7 @@ pub enum AddressingMode {
     Absolute_Y,
     Indirect_X,
     Indirect_Y,
+    Indirect_ZeroPage, // (zp), 65C02 only
+    ZeroPage_Relative, // zp,rel for BBR/BBS, 65C02 only
     Implied,
 }
@@ -0,0 +1,390 @@
+use bitflags::bitflags;
+
+use crate::region::Region;
+
+bitflags! {
+    // $4015. writing enables the channels, reading tells which length counters are still
+    // running and which interrupts are pending. bit 5 isn't driven
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ApuStatus: u8 {
+        const PULSE1          = 0b0000_0001;
+        const PULSE2          = 0b0000_0010;
+        const TRIANGLE        = 0b0000_0100;
+        const NOISE           = 0b0000_1000;
+        const DMC             = 0b0001_0000;
+        const FRAME_INTERRUPT = 0b0100_0000;
+        const DMC_INTERRUPT   = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    // $4017, write only
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FrameCounter: u8 {
+        const IRQ_INHIBIT = 0b0100_0000;
+        const FIVE_STEP   = 0b1000_0000; // 0 -> 4 step sequence, 1 -> 5 step sequence
+    }
+}
+
+// what the length counters get loaded with, indexed by the top 5 bits of the last register
+// of each channel
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// where the frame counter steps, in CPU cycles since it was last reset. the 4 step sequence
+// raises its interrupt on the fourth step, the 5 step one does nothing on its fourth
+const NTSC_FRAME_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+// CPU cycles per DMC output bit, indexed by the low 4 bits of $4010
+const NTSC_DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// the APU as the CPU sees it through $4000-$4013, $4015 and $4017.
+// everything but $4015 is write only:
+//  $4000-$4003 -> pulse 1
+//  $4004-$4007 -> pulse 2
+//  $4008-$400B -> triangle
+//  $400C-$400F -> noise
+//  $4010-$4013 -> DMC
+pub struct Apu {
+    pub registers: [u8; 0x14], // $4000-$4013 as last written
+    pub enabled: ApuStatus,    // only the channel bits
+    pub frame_counter: FrameCounter,
+    pub frame_interrupt: bool,
+    pub dmc_interrupt: bool,
+
+    pub region: Region,
+
+    length_counters: [u8; 4], // pulse 1, pulse 2, triangle, noise
+    frame_cycle: u32,         // CPU cycles since the frame counter was reset
+
+    // the DMC plays samples straight from CPU memory, one byte every 8 output bits
+    dmc_address: u16,
+    dmc_bytes_remaining: u16,
+    dmc_buffer_full: bool,
+    dmc_bits: u8,   // left in the output shifter
+    dmc_timer: u16, // CPU cycles until the next output bit
+    dmc_fetch: Option<u16>,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            registers: [0; 0x14],
+            enabled: ApuStatus::empty(),
+            frame_counter: FrameCounter::empty(),
+            frame_interrupt: false,
+            dmc_interrupt: false,
+            region: Region::Ntsc,
+            length_counters: [0; 4],
+            frame_cycle: 0,
+            dmc_address: 0xc000,
+            dmc_bytes_remaining: 0,
+            dmc_buffer_full: false,
+            dmc_bits: 0,
+            dmc_timer: 0,
+            dmc_fetch: None,
+        }
+    }
+
+    // the frame counter interrupt and the DMC's end of sample interrupt share the IRQ line
+    pub fn irq_line(&self) -> bool {
+        self.frame_interrupt || self.dmc_interrupt
+    }
+
+    // runs the APU for one CPU cycle
+    pub fn tick(&mut self) {
+        self.tick_frame_counter();
+        self.tick_dmc();
+    }
+
+    // the address of a sample byte the DMC wants, if it's waiting for one. the fetch goes over
+    // the CPU bus and takes it away from the CPU for a few cycles
+    pub fn take_dmc_fetch(&mut self) -> Option<u16> {
+        self.dmc_fetch.take()
+    }
+
+    fn tick_frame_counter(&mut self) {
+        let steps = match self.region {
+            Region::Ntsc => NTSC_FRAME_STEPS,
+            Region::Pal => PAL_FRAME_STEPS,
+        };
+        let five_step = self.frame_counter.contains(FrameCounter::FIVE_STEP);
+
+        self.frame_cycle += 1;
+        match steps.iter().position(|&step| step == self.frame_cycle) {
+            Some(1) => self.clock_half_frame(),
+            Some(3) if !five_step => {
+                self.clock_half_frame();
+                if !self.frame_counter.contains(FrameCounter::IRQ_INHIBIT) {
+                    self.frame_interrupt = true;
+                }
+                self.frame_cycle = 0;
+            }
+            Some(4) => {
+                self.clock_half_frame();
+                self.frame_cycle = 0;
+            }
+            // the quarter frames only clock the envelopes and the linear counter
+            _ => {}
+        }
+    }
+
+    // length counters count down unless their channel's halt flag is set
+    fn clock_half_frame(&mut self) {
+        let halted = [
+            self.registers[0x00] & 0b0010_0000 != 0,
+            self.registers[0x04] & 0b0010_0000 != 0,
+            self.registers[0x08] & 0b1000_0000 != 0,
+            self.registers[0x0c] & 0b0010_0000 != 0,
+        ];
+        for (counter, halted) in self.length_counters.iter_mut().zip(halted) {
+            if *counter > 0 && !halted {
+                *counter -= 1;
+            }
+        }
+    }
+
+    fn tick_dmc(&mut self) {
+        // the reader refills the sample buffer as soon as it's empty
+        if !self.dmc_buffer_full && self.dmc_bytes_remaining > 0 {
+            self.dmc_fetch = Some(self.dmc_address);
+            self.dmc_buffer_full = true;
+            // the sample wraps from the top of memory to $8000
+            self.dmc_address = self.dmc_address.checked_add(1).unwrap_or(0x8000);
+            self.dmc_bytes_remaining -= 1;
+            if self.dmc_bytes_remaining == 0 {
+                if self.registers[0x10] & 0b0100_0000 != 0 {
+                    self.restart_dmc();
+                } else if self.registers[0x10] & 0b1000_0000 != 0 {
+                    self.dmc_interrupt = true;
+                }
+            }
+        }
+
+        if self.dmc_timer > 0 {
+            self.dmc_timer -= 1;
+            return;
+        }
+        let rates = match self.region {
+            Region::Ntsc => NTSC_DMC_RATES,
+            Region::Pal => PAL_DMC_RATES,
+        };
+        self.dmc_timer = rates[(self.registers[0x10] & 0x0f) as usize] - 1;
+        if self.dmc_bits == 0 {
+            // a new output cycle starts and empties the buffer into the shifter
+            self.dmc_bits = 8;
+            self.dmc_buffer_full = false;
+        }
+        self.dmc_bits -= 1;
+    }
+
+    // $4012 is the sample address in units of 64 bytes from $C000, $4013 the length in units
+    // of 16 bytes, plus one
+    fn restart_dmc(&mut self) {
+        self.dmc_address = 0xc000 + self.registers[0x12] as u16 * 64;
+        self.dmc_bytes_remaining = self.registers[0x13] as u16 * 16 + 1;
+    }
+
+    // reading $4015 acknowledges the frame interrupt, but not the DMC one
+    pub fn read_status(&mut self) -> u8 {
+        let data = self.peek_status();
+        self.frame_interrupt = false;
+        data
+    }
+
+    pub fn peek_status(&self) -> u8 {
+        let mut status = ApuStatus::empty();
+        for (i, channel) in [
+            ApuStatus::PULSE1,
+            ApuStatus::PULSE2,
+            ApuStatus::TRIANGLE,
+            ApuStatus::NOISE,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            status.set(channel, self.length_counters[i] > 0);
+        }
+        status.set(ApuStatus::DMC, self.dmc_bytes_remaining > 0);
+        status.set(ApuStatus::FRAME_INTERRUPT, self.frame_interrupt);
+        status.set(ApuStatus::DMC_INTERRUPT, self.dmc_interrupt);
+        status.bits()
+    }
+
+    // `addr` is one of $4000-$4013, $4015 or $4017
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4013 => {
+                self.registers[(addr - 0x4000) as usize] = data;
+                match addr {
+                    // the last register of a channel reloads its length counter, if it's enabled
+                    0x4003 | 0x4007 | 0x400b | 0x400f => {
+                        let channel = (addr - 0x4000) as usize / 4;
+                        if self.enabled.bits() & (1 << channel) != 0 {
+                            self.length_counters[channel] = LENGTH_TABLE[data as usize >> 3];
+                        }
+                    }
+                    // turning the DMC's IRQ enable off also acknowledges its interrupt
+                    0x4010 if data & 0b1000_0000 == 0 => self.dmc_interrupt = false,
+                    _ => {}
+                }
+            }
+            0x4015 => self.write_status(data),
+            // restarts the sequence. the 5 step mode clocks the length counters right away
+            0x4017 => {
+                self.frame_counter = FrameCounter::from_bits_truncate(data);
+                if self.frame_counter.contains(FrameCounter::IRQ_INHIBIT) {
+                    self.frame_interrupt = false;
+                }
+                self.frame_cycle = 0;
+                if self.frame_counter.contains(FrameCounter::FIVE_STEP) {
+                    self.clock_half_frame();
+                }
+            }
+            _ => unreachable!("{:04x} isn't an APU register", addr),
+        }
+    }
+
+    // disabling a channel silences it right away by clearing its length counter. enabling the
+    // DMC restarts the sample, but only if the last one already finished
+    fn write_status(&mut self, data: u8) {
+        self.enabled = ApuStatus::from_bits_truncate(data & 0b0001_1111);
+        for (i, counter) in self.length_counters.iter_mut().enumerate() {
+            if data & (1 << i) == 0 {
+                *counter = 0;
+            }
+        }
+
+        if !self.enabled.contains(ApuStatus::DMC) {
+            self.dmc_bytes_remaining = 0;
+        } else if self.dmc_bytes_remaining == 0 {
+            self.restart_dmc();
+        }
+        self.dmc_interrupt = false;
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_length_counters_only_load_when_enabled() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4003, 0b0000_1000);
+        assert_eq!(apu.peek_status(), 0);
+
+        apu.write_register(0x4015, 0b0000_0101); // pulse 1 and triangle
+        apu.write_register(0x4003, 0b0000_1000);
+        apu.write_register(0x4007, 0b0000_1000);
+        apu.write_register(0x400b, 0b0000_1000);
+        assert_eq!(apu.peek_status(), 0b0000_0101);
+        assert_eq!(apu.length_counters[0], 254);
+
+        // disabling clears the counter
+        apu.write_register(0x4015, 0b0000_0100);
+        assert_eq!(apu.peek_status(), 0b0000_0100);
+    }
+
+    #[test]
+    fn test_reading_status_acknowledges_the_frame_interrupt() {
+        let mut apu = Apu::new();
+        apu.frame_interrupt = true;
+        apu.dmc_interrupt = true;
+        assert_eq!(apu.peek_status(), 0b1100_0000);
+        assert_eq!(apu.read_status(), 0b1100_0000);
+        assert_eq!(apu.read_status(), 0b1000_0000);
+
+        apu.frame_interrupt = true;
+        apu.write_register(0x4017, 0b0100_0000);
+        assert!(!apu.frame_interrupt);
+        apu.write_register(0x4015, 0);
+        assert!(!apu.dmc_interrupt);
+    }
+
+    #[test]
+    fn test_enabling_the_dmc_starts_a_sample() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4013, 0x01);
+        apu.write_register(0x4015, 0b0001_0000);
+        assert_eq!(apu.dmc_bytes_remaining, 17);
+        assert_eq!(apu.peek_status(), 0b0001_0000);
+
+        apu.write_register(0x4015, 0);
+        assert_eq!(apu.peek_status(), 0);
+    }
+
+    #[test]
+    fn test_frame_interrupt_every_4_step_sequence() {
+        let mut apu = Apu::new();
+        for _ in 0..29828 {
+            apu.tick();
+        }
+        assert!(!apu.irq_line());
+        apu.tick();
+        assert!(apu.irq_line());
+        apu.read_status();
+        assert!(!apu.irq_line());
+
+        // inhibited, or in 5 step mode, it never fires
+        apu.write_register(0x4017, 0b0100_0000);
+        for _ in 0..29829 {
+            apu.tick();
+        }
+        assert!(!apu.irq_line());
+        apu.write_register(0x4017, 0b1000_0000);
+        for _ in 0..2 * 37281 {
+            apu.tick();
+        }
+        assert!(!apu.irq_line());
+    }
+
+    #[test]
+    fn test_length_counters_count_down_on_half_frames() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4015, 0b0000_0011);
+        apu.write_register(0x4003, 0b0001_1000); // length 2
+        apu.write_register(0x4004, 0b0010_0000); // pulse 2 halted
+        apu.write_register(0x4007, 0b0001_1000);
+
+        for _ in 0..14913 {
+            apu.tick();
+        }
+        assert_eq!(apu.length_counters, [1, 2, 0, 0]);
+        // $4017 in 5 step mode clocks them immediately
+        apu.write_register(0x4017, 0b1000_0000);
+        assert_eq!(apu.peek_status(), 0b0000_0010);
+    }
+
+    #[test]
+    fn test_dmc_fetches_the_sample_and_interrupts_at_the_end() {
+        let mut apu = Apu::new();
+        apu.write_register(0x4010, 0b1000_1111); // IRQ on, fastest rate
+        apu.write_register(0x4012, 0x01);
+        apu.write_register(0x4013, 0x00); // a single byte
+        apu.write_register(0x4015, 0b0001_0000);
+
+        apu.tick();
+        assert_eq!(apu.take_dmc_fetch(), Some(0xc040));
+        assert!(apu.dmc_interrupt);
+        assert_eq!(apu.peek_status() & 0b1001_0000, 0b1000_0000);
+        for _ in 0..1000 {
+            apu.tick();
+        }
+        assert_eq!(apu.take_dmc_fetch(), None);
+    }
+}
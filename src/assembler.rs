@@ -0,0 +1,661 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::addressing_mode::AddressingMode;
+use crate::opcodes::{self, OPS_CODES, OpCode};
+use crate::variant::Variant;
+
+// a small two pass assembler for tests and tools, it knows whatever the opcode tables know.
+//
+//   ptr = $20               ; constants
+//   start:  LDX #$03        ; labels, optionally on the same line as an instruction
+//   loop:   LDA (ptr),Y
+//           DEX
+//           BNE loop        ; branches take the target, the offset is worked out here
+//           JMP (vector)
+//   vector: .word start     ; .byte and .word take comma separated lists
+//           .org $fffc      ; pads with zeros up to the address
+//
+// numbers are $hex, %binary or decimal. operands can add and subtract, `<` and `>` take the low
+// and high byte, `*` is the address of the current line. addresses that fit in a byte use the
+// zero page form when there is one, unless they depend on a label defined further down, since
+// the first pass can't know how big those will be
+#[derive(Debug, PartialEq)]
+pub enum AsmError {
+    UnknownInstruction { line: usize, mnemonic: String },
+    // the instruction exists, but not with this operand
+    InvalidOperand { line: usize, operand: String },
+    UnknownLabel { line: usize, label: String },
+    DuplicateLabel { line: usize, label: String },
+    // `a` always means the accumulator, so it can't name a label
+    ReservedLabel { line: usize, label: String },
+    // doesn't fit in the byte or word it's assembled into
+    OutOfRange { line: usize, value: i32 },
+    BranchOutOfRange { line: usize, offset: i32 },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::UnknownInstruction { line, mnemonic } => {
+                write!(f, "line {}: unknown instruction {}", line, mnemonic)
+            }
+            AsmError::InvalidOperand { line, operand } => {
+                write!(f, "line {}: invalid operand {}", line, operand)
+            }
+            AsmError::UnknownLabel { line, label } => {
+                write!(f, "line {}: unknown label {}", line, label)
+            }
+            AsmError::DuplicateLabel { line, label } => {
+                write!(f, "line {}: label {} is already defined", line, label)
+            }
+            AsmError::ReservedLabel { line, label } => {
+                write!(f, "line {}: {} is the accumulator, not a label", line, label)
+            }
+            AsmError::OutOfRange { line, value } => {
+                write!(f, "line {}: value {} is out of range", line, value)
+            }
+            AsmError::BranchOutOfRange { line, offset } => {
+                write!(f, "line {}: branch offset {} is out of range", line, offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// assembles for the NMOS 6502, the first byte of the result goes to `origin`
+pub fn assemble(origin: u16, source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble_for(Variant::Nmos6502, origin, source)
+}
+
+pub fn assemble_for(variant: Variant, origin: u16, source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        table: opcodes::table(variant),
+        symbols: HashMap::new(),
+        last_pass: false,
+    };
+    // the first pass only finds out where the labels are
+    assembler.pass(origin, source)?;
+    assembler.last_pass = true;
+    assembler.pass(origin, source)
+}
+
+#[derive(Debug)]
+enum Operand<'a> {
+    None,
+    Accumulator,
+    Immediate(&'a str),
+    Direct(&'a str),
+    DirectX(&'a str),
+    DirectY(&'a str),
+    Indirect(&'a str),
+    IndirectX(&'a str),
+    IndirectY(&'a str),
+    // zp,target for BBR/BBS
+    Pair(&'a str, &'a str),
+}
+
+// what goes after the opcode byte
+enum Encoding<'a> {
+    None,
+    Byte(&'a str),
+    Word(&'a str),
+    Relative(&'a str),
+    ZeroPageRelative(&'a str, &'a str),
+}
+
+struct Value {
+    value: i32,
+    // depends on a label that isn't defined above this line
+    forward: bool,
+}
+
+struct Assembler {
+    table: &'static [OpCode; 256],
+    symbols: HashMap<String, (i32, usize)>, // name -> (value, line it's defined on)
+    last_pass: bool,
+}
+
+impl Assembler {
+    fn pass(&mut self, origin: u16, source: &str) -> Result<Vec<u8>, AsmError> {
+        let mut out = vec![];
+        let mut pc = origin;
+
+        for (i, text) in source.lines().enumerate() {
+            let line = i + 1;
+            let mut text = text.split(';').next().unwrap_or("").trim();
+
+            while let Some((label, rest)) = split_label(text) {
+                self.define(label, pc as i32, line)?;
+                text = rest.trim();
+            }
+            if text.is_empty() {
+                continue;
+            }
+
+            if let Some((name, expr)) = text.split_once('=') {
+                let name = name.trim();
+                if is_identifier(name) {
+                    let value = self.eval(expr.trim(), pc, line)?;
+                    self.define(name, value.value, line)?;
+                    continue;
+                }
+            }
+
+            let (mnemonic, operand) = match text.split_once(char::is_whitespace) {
+                Some((mnemonic, operand)) => (mnemonic, operand.trim()),
+                None => (text, ""),
+            };
+
+            let bytes = if mnemonic.starts_with('.') {
+                self.directive(mnemonic, operand, pc, line)?
+            } else {
+                self.instruction(mnemonic, operand, pc, line)?
+            };
+            pc = pc.wrapping_add(bytes.len() as u16);
+            out.extend(bytes);
+        }
+
+        Ok(out)
+    }
+
+    fn define(&mut self, name: &str, value: i32, line: usize) -> Result<(), AsmError> {
+        if name.eq_ignore_ascii_case("a") {
+            return Err(AsmError::ReservedLabel {
+                line,
+                label: name.to_string(),
+            });
+        }
+        if !self.last_pass && self.symbols.contains_key(name) {
+            return Err(AsmError::DuplicateLabel {
+                line,
+                label: name.to_string(),
+            });
+        }
+        self.symbols.insert(name.to_string(), (value, line));
+        Ok(())
+    }
+
+    fn directive(
+        &self,
+        directive: &str,
+        operand: &str,
+        pc: u16,
+        line: usize,
+    ) -> Result<Vec<u8>, AsmError> {
+        let mut bytes = vec![];
+        match directive.to_ascii_lowercase().as_str() {
+            ".byte" => {
+                for expr in operand.split(',') {
+                    bytes.push(self.byte(expr.trim(), pc, line)?);
+                }
+            }
+            ".word" => {
+                for expr in operand.split(',') {
+                    bytes.extend(self.word(expr.trim(), pc, line)?.to_le_bytes());
+                }
+            }
+            ".org" => {
+                let target = self.eval(operand, pc, line)?;
+                if target.forward || target.value < pc as i32 || target.value > 0xffff {
+                    return Err(AsmError::InvalidOperand {
+                        line,
+                        operand: operand.to_string(),
+                    });
+                }
+                bytes.resize((target.value - pc as i32) as usize, 0);
+            }
+            _ => {
+                return Err(AsmError::UnknownInstruction {
+                    line,
+                    mnemonic: directive.to_string(),
+                });
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operand_text: &str,
+        pc: u16,
+        line: usize,
+    ) -> Result<Vec<u8>, AsmError> {
+        let candidates = self.candidates(mnemonic);
+        if candidates.is_empty() {
+            return Err(AsmError::UnknownInstruction {
+                line,
+                mnemonic: mnemonic.to_string(),
+            });
+        }
+
+        let operand = parse_operand(operand_text);
+        let fits_zero_page = match operand {
+            Operand::Direct(expr) | Operand::DirectX(expr) | Operand::DirectY(expr) => {
+                let value = self.eval(expr, pc, line)?;
+                !value.forward && (0..=0xff).contains(&value.value)
+            }
+            _ => false,
+        };
+
+        // the zero page form wins if both exist and the address allows it
+        let mut found = None;
+        for op in candidates {
+            let Some(encoding) = encoding(op, &operand) else {
+                continue;
+            };
+            let zero_page = matches!(
+                op.addr_mode,
+                AddressingMode::ZeroPage | AddressingMode::ZeroPage_X | AddressingMode::ZeroPage_Y
+            );
+            if zero_page && !fits_zero_page {
+                continue;
+            }
+            if found.is_none() || zero_page {
+                found = Some((op, encoding));
+            }
+            if zero_page {
+                break;
+            }
+        }
+        let Some((op, encoding)) = found else {
+            return Err(AsmError::InvalidOperand {
+                line,
+                operand: operand_text.to_string(),
+            });
+        };
+
+        let mut bytes = vec![op.code];
+        match encoding {
+            Encoding::None => {}
+            Encoding::Byte(expr) => bytes.push(self.byte(expr, pc, line)?),
+            Encoding::Word(expr) => bytes.extend(self.word(expr, pc, line)?.to_le_bytes()),
+            Encoding::Relative(target) => {
+                bytes.push(self.offset(target, pc.wrapping_add(2), pc, line)?)
+            }
+            Encoding::ZeroPageRelative(zero_page, target) => {
+                bytes.push(self.byte(zero_page, pc, line)?);
+                bytes.push(self.offset(target, pc.wrapping_add(3), pc, line)?);
+            }
+        }
+        Ok(bytes)
+    }
+
+    // every opcode with this name, documented ones first. "*NOP" only picks the illegal ones,
+    // a plain "NOP" only falls back to them if there's no documented instruction by that name
+    fn candidates(&self, mnemonic: &str) -> Vec<&'static OpCode> {
+        let illegal_only = mnemonic.starts_with('*');
+        let name = mnemonic.trim_start_matches('*');
+        let named: Vec<&'static OpCode> = self
+            .table
+            .iter()
+            .filter(|op| op.name.trim_start_matches('*').eq_ignore_ascii_case(name))
+            .collect();
+
+        let documented: Vec<&'static OpCode> = named
+            .iter()
+            .copied()
+            .filter(|op| !op.name.starts_with('*'))
+            .collect();
+        let mut candidates = if illegal_only || documented.is_empty() {
+            named
+                .into_iter()
+                .filter(|op| op.name.starts_with('*'))
+                .collect()
+        } else {
+            documented
+        };
+        // the 65C02 has a handful of NOPs of every size, the one the NMOS chip had is the
+        // one anybody means
+        candidates.sort_by_key(|op| OPS_CODES[op.code as usize].name != op.name);
+        candidates
+    }
+
+    fn eval(&self, expr: &str, pc: u16, line: usize) -> Result<Value, AsmError> {
+        let invalid = || AsmError::InvalidOperand {
+            line,
+            operand: expr.to_string(),
+        };
+
+        let (part, body) = match expr.chars().next() {
+            Some('<') => (Some(false), &expr[1..]),
+            Some('>') => (Some(true), &expr[1..]),
+            _ => (None, expr),
+        };
+
+        let mut value = 0;
+        let mut forward = false;
+        let mut sign = 1;
+        let mut rest = body.trim();
+        if let Some(negated) = rest.strip_prefix('-') {
+            sign = -1;
+            rest = negated.trim();
+        }
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let term = rest[..end].trim();
+
+            let term_value = match term.chars().next() {
+                Some('$') => i32::from_str_radix(&term[1..], 16).map_err(|_| invalid())?,
+                Some('%') => i32::from_str_radix(&term[1..], 2).map_err(|_| invalid())?,
+                Some('0'..='9') => term.parse::<i32>().map_err(|_| invalid())?,
+                Some('*') if term.len() == 1 => pc as i32,
+                _ if is_identifier(term) => match self.symbols.get(term) {
+                    Some(&(value, defined)) => {
+                        forward |= defined >= line;
+                        value
+                    }
+                    None if !self.last_pass => {
+                        forward = true;
+                        0
+                    }
+                    None => {
+                        return Err(AsmError::UnknownLabel {
+                            line,
+                            label: term.to_string(),
+                        });
+                    }
+                },
+                _ => return Err(invalid()),
+            };
+            value += sign * term_value;
+
+            rest = rest[end..].trim();
+            match rest.chars().next() {
+                Some('+') => sign = 1,
+                Some('-') => sign = -1,
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+
+        let value = match part {
+            Some(false) => value & 0xff,
+            Some(true) => (value >> 8) & 0xff,
+            None => value,
+        };
+        Ok(Value { value, forward })
+    }
+
+    // nothing is out of range on the first pass, the labels might not be known yet
+    fn byte(&self, expr: &str, pc: u16, line: usize) -> Result<u8, AsmError> {
+        let value = self.eval(expr, pc, line)?.value;
+        if self.last_pass && !(-0x80..=0xff).contains(&value) {
+            return Err(AsmError::OutOfRange { line, value });
+        }
+        Ok(value as u8)
+    }
+
+    fn word(&self, expr: &str, pc: u16, line: usize) -> Result<u16, AsmError> {
+        let value = self.eval(expr, pc, line)?.value;
+        if self.last_pass && !(-0x8000..=0xffff).contains(&value) {
+            return Err(AsmError::OutOfRange { line, value });
+        }
+        Ok(value as u16)
+    }
+
+    // branches are relative to the address of the next instruction
+    fn offset(&self, target: &str, next: u16, pc: u16, line: usize) -> Result<u8, AsmError> {
+        let offset = self.eval(target, pc, line)?.value - next as i32;
+        if self.last_pass && !(-0x80..=0x7f).contains(&offset) {
+            return Err(AsmError::BranchOutOfRange { line, offset });
+        }
+        Ok(offset as u8)
+    }
+}
+
+// how `op` encodes `operand`, None if it can't take it. the table describes branches and
+// JMP as implied, so those are told apart by size and opcode
+fn encoding<'a>(op: &OpCode, operand: &Operand<'a>) -> Option<Encoding<'a>> {
+    let encoding = match (op.addr_mode, operand) {
+        (AddressingMode::Implied, Operand::None) if op.len == 1 => Encoding::None,
+        (AddressingMode::Implied, Operand::Accumulator)
+            if op.len == 1 && matches!(op.name, "ASL" | "LSR" | "ROL" | "ROR" | "INC" | "DEC") =>
+        {
+            Encoding::None
+        }
+        (AddressingMode::Implied, Operand::Direct(target)) if op.len == 2 => {
+            Encoding::Relative(target)
+        }
+        (AddressingMode::Implied, Operand::Direct(target)) if op.code == 0x4c => {
+            Encoding::Word(target)
+        }
+        (AddressingMode::Implied, Operand::Indirect(target)) if op.code == 0x6c => {
+            Encoding::Word(target)
+        }
+        (AddressingMode::Implied, Operand::IndirectX(target)) if op.code == 0x7c => {
+            Encoding::Word(target)
+        }
+        (AddressingMode::Immediate, Operand::Immediate(value)) => Encoding::Byte(value),
+        (AddressingMode::ZeroPage, Operand::Direct(addr))
+        | (AddressingMode::ZeroPage_X, Operand::DirectX(addr))
+        | (AddressingMode::ZeroPage_Y, Operand::DirectY(addr))
+        | (AddressingMode::Indirect_X, Operand::IndirectX(addr))
+        | (AddressingMode::Indirect_Y, Operand::IndirectY(addr))
+        | (AddressingMode::Indirect_ZeroPage, Operand::Indirect(addr)) => Encoding::Byte(addr),
+        (AddressingMode::Absolute, Operand::Direct(addr))
+        | (AddressingMode::Absolute_X, Operand::DirectX(addr))
+        | (AddressingMode::Absolute_Y, Operand::DirectY(addr)) => Encoding::Word(addr),
+        (AddressingMode::ZeroPage_Relative, Operand::Pair(zero_page, target)) => {
+            Encoding::ZeroPageRelative(zero_page, target)
+        }
+        _ => return None,
+    };
+    Some(encoding)
+}
+
+fn parse_operand(text: &str) -> Operand<'_> {
+    if text.is_empty() {
+        return Operand::None;
+    }
+    if text.eq_ignore_ascii_case("a") {
+        return Operand::Accumulator;
+    }
+    if let Some(value) = text.strip_prefix('#') {
+        return Operand::Immediate(value.trim());
+    }
+
+    let index = |text: &str| text.trim().to_ascii_uppercase();
+    if let Some(inner) = text.strip_prefix('(')
+        && let Some((inner, after)) = inner.rsplit_once(')')
+    {
+        let after = after.trim();
+        if let Some(y) = after.strip_prefix(',')
+            && index(y) == "Y"
+        {
+            return Operand::IndirectY(inner.trim());
+        }
+        if after.is_empty() {
+            return match inner.rsplit_once(',') {
+                Some((inner, x)) if index(x) == "X" => Operand::IndirectX(inner.trim()),
+                _ => Operand::Indirect(inner.trim()),
+            };
+        }
+    }
+
+    match text.rsplit_once(',') {
+        Some((addr, x)) if index(x) == "X" => Operand::DirectX(addr.trim()),
+        Some((addr, y)) if index(y) == "Y" => Operand::DirectY(addr.trim()),
+        Some((zero_page, target)) => Operand::Pair(zero_page.trim(), target.trim()),
+        None => Operand::Direct(text),
+    }
+}
+
+// "loop: DEX" -> ("loop", "DEX")
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (label, rest) = text.split_once(':')?;
+    is_identifier(label).then_some((label, rest))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn asm(source: &str) -> Vec<u8> {
+        assemble(0x0600, source).unwrap()
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        assert_eq!(asm("LDA #$10"), vec![0xa9, 0x10]);
+        assert_eq!(asm("LDA $10"), vec![0xa5, 0x10]);
+        assert_eq!(asm("LDA $10,X"), vec![0xb5, 0x10]);
+        assert_eq!(asm("LDX $10,Y"), vec![0xb6, 0x10]);
+        assert_eq!(asm("LDA $1234"), vec![0xad, 0x34, 0x12]);
+        assert_eq!(asm("LDA $1234,X"), vec![0xbd, 0x34, 0x12]);
+        assert_eq!(asm("LDA $1234,Y"), vec![0xb9, 0x34, 0x12]);
+        assert_eq!(asm("LDA ($10,X)"), vec![0xa1, 0x10]);
+        assert_eq!(asm("LDA ($10),Y"), vec![0xb1, 0x10]);
+        assert_eq!(asm("ASL A"), vec![0x0a]);
+        assert_eq!(asm("ASL"), vec![0x0a]);
+        assert_eq!(asm("INX"), vec![0xe8]);
+        assert_eq!(asm("JMP $1234"), vec![0x4c, 0x34, 0x12]);
+        assert_eq!(asm("JMP ($1234)"), vec![0x6c, 0x34, 0x12]);
+        assert_eq!(asm("JSR $1234"), vec![0x20, 0x34, 0x12]);
+        // there's no LDA zp,Y, so it has to be absolute
+        assert_eq!(asm("LDA $10,Y"), vec![0xb9, 0x10, 0x00]);
+        // lowercase works too
+        assert_eq!(asm("sta ($10), y"), vec![0x91, 0x10]);
+    }
+
+    #[test]
+    fn test_labels_and_branches() {
+        let program = asm("
+            start:  LDX #$03
+            loop:   DEX
+                    BNE loop
+                    BEQ done
+                    JMP start
+            done:   BRK
+        ");
+        assert_eq!(
+            program,
+            vec![
+                0xa2, 0x03, // LDX #$03
+                0xca, // DEX
+                0xd0, 0xfd, // BNE loop
+                0xf0, 0x03, // BEQ done
+                0x4c, 0x00, 0x06, // JMP start
+                0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forward_labels_are_absolute() {
+        // `data` is only known after the first pass, so it can't shrink to zero page
+        let program = assemble(0x0000, "LDA data\ndata: .byte $42").unwrap();
+        assert_eq!(program, vec![0xad, 0x03, 0x00, 0x42]);
+
+        let program = assemble(0x0000, "ptr = $20\nLDA ptr\nLDA (ptr),Y").unwrap();
+        assert_eq!(program, vec![0xa5, 0x20, 0xb1, 0x20]);
+    }
+
+    #[test]
+    fn test_directives_and_expressions() {
+        let program = asm("
+            table:  .byte 1, $02, %11, <table, >table
+                    .word table+2, $beef
+                    LDA #>*
+                    .org $0610
+                    .byte -1
+        ");
+        assert_eq!(
+            &program[..9],
+            &[1, 2, 3, 0x00, 0x06, 0x02, 0x06, 0xef, 0xbe]
+        );
+        assert_eq!(&program[9..11], &[0xa9, 0x06]);
+        assert!(program[11..0x10].iter().all(|&byte| byte == 0));
+        assert_eq!(&program[0x10..], &[0xff]);
+    }
+
+    #[test]
+    fn test_65c02() {
+        let program = assemble_for(
+            Variant::Wdc65C02,
+            0x0200,
+            "
+                    STZ $10
+                    LDA ($10)
+                    BBR0 $10, here
+            here:   JMP ($1234,X)
+                    NOP
+            ",
+        )
+        .unwrap();
+        assert_eq!(
+            program,
+            vec![
+                0x64, 0x10, 0xb2, 0x10, 0x0f, 0x10, 0x00, 0x7c, 0x34, 0x12, 0xea
+            ]
+        );
+        // illegal opcodes only exist on the NMOS chip. the star is optional, it only forces
+        // the illegal form where a legal one has the same name
+        assert_eq!(asm("*LAX $10"), vec![0xa7, 0x10]);
+        assert_eq!(asm("LAX $10"), vec![0xa7, 0x10]);
+        assert!(assemble_for(Variant::Wdc65C02, 0, "LAX $10").is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble(0, "FOO"),
+            Err(AsmError::UnknownInstruction {
+                line: 1,
+                mnemonic: "FOO".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(0, "\nJMP nowhere"),
+            Err(AsmError::UnknownLabel {
+                line: 2,
+                label: "nowhere".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(0, "loop: NOP\nloop: NOP"),
+            Err(AsmError::DuplicateLabel {
+                line: 2,
+                label: "loop".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(0, "a: NOP\nJMP a"),
+            Err(AsmError::ReservedLabel {
+                line: 1,
+                label: "a".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(0, "INX #$10"),
+            Err(AsmError::InvalidOperand {
+                line: 1,
+                operand: "#$10".to_string()
+            })
+        );
+        assert_eq!(
+            assemble(0, "LDA #$100"),
+            Err(AsmError::OutOfRange {
+                line: 1,
+                value: 0x100
+            })
+        );
+        assert_eq!(
+            assemble(0, "BNE far\n.org $100\nfar: NOP"),
+            Err(AsmError::BranchOutOfRange {
+                line: 1,
+                offset: 0xfe
+            })
+        );
+    }
+}
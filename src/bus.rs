@@ -1,26 +1,171 @@
-use crate::{mem::Mem, rom::Rom};
+use std::collections::VecDeque;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::{
+    apu::Apu, dma::Dma, joypad::Joypad, mem::Mem, ppu::NesPPU, region::Region, rom::Rom, save,
+};
 
 const RAM_START: u16 = 0x0000;
 const RAM_MIRROR_END: u16 = 0x1FFF; // 1 decimal less than 0x2000
 const PPU_REGISTERS_START: u16 = 0x2000;
 const PPU_REGISTERS_MIRROR_END: u16 = 0x3FFF;
+const APU_REGISTERS_START: u16 = 0x4000;
+const APU_REGISTERS_END: u16 = 0x4013;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const JOYPAD1: u16 = 0x4016;
+const JOYPAD2: u16 = 0x4017; // the APU frame counter when written
+const PRG_RAM_START: u16 = 0x6000;
+const PRG_RAM_END: u16 = 0x7FFF;
 const PRG_ROM_START: u16 = 0x8000;
 const PRG_ROM_END: u16 = 0xFFFF;
 
 const RAM_MIRROR_MASK: u16 = 0x07FF; // keep low 11 bits
 const PPU_REG_MASK: u16 = 0x2007;
 
+// the bits of a register read that nothing drives, they keep whatever was on the bus before
+const APU_STATUS_OPEN_BUS: u8 = 0b0010_0000;
+const JOYPAD_OPEN_BUS: u8 = 0b1110_0000;
+
+// something attached with `Bus::attach`
+struct Attached {
+    range: RangeInclusive<u16>,
+    device: Box<dyn Mem>,
+}
+
 pub struct Bus {
     cpu_vram: [u8; 2048], // RAM only uses 2KB of space
     rom: Rom,
+    prg_ram: Vec<u8>, // on the cartridge, battery backed on some
+    ppu: NesPPU,
+    apu: Apu,
+    joypad1: Joypad,
+    joypad2: Joypad,
+    dma: VecDeque<Dma>, // started since the CPU last looked
+    region: Region,
+    dot_fifths: usize, // PPU time owed, PAL runs a fifth of a dot past every CPU cycle
+    // the last value on the data bus. the lines hold it for a while after every access, so
+    // reading from where nothing answers gives it back
+    open_bus: u8,
+    devices: Vec<Attached>,
 }
 
 impl Bus {
     pub fn new(rom: Rom) -> Self {
-        Bus {
+        let ppu = NesPPU::new(rom.chr_rom.clone(), rom.screen_mirroring);
+        let region = rom.region;
+        let mut bus = Bus {
             cpu_vram: [0; 2048],
+            prg_ram: vec![0; rom.prg_ram_size],
             rom,
+            ppu,
+            apu: Apu::new(),
+            joypad1: Joypad::new(),
+            joypad2: Joypad::new(),
+            dma: VecDeque::new(),
+            region,
+            dot_fifths: 0,
+            open_bus: 0,
+            devices: vec![],
+        };
+        bus.set_region(region);
+        bus
+    }
+
+    // the header rarely says, so this can be set by hand
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.region = region;
+        self.apu.region = region;
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn ppu(&self) -> &NesPPU {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut NesPPU {
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    pub fn joypad1_mut(&mut self) -> &mut Joypad {
+        &mut self.joypad1
+    }
+
+    pub fn joypad2_mut(&mut self) -> &mut Joypad {
+        &mut self.joypad2
+    }
+
+    // maps `device` over `range`, on top of whatever the NES has there. where attached ranges
+    // overlap the last one wins. the device gets the full address and is ticked with the rest
+    // of the bus, its interrupt lines, faults and DMAs go on to the CPU. returns an id for
+    // `device`
+    pub fn attach(&mut self, range: RangeInclusive<u16>, device: Box<dyn Mem>) -> usize {
+        self.devices.push(Attached { range, device });
+        self.devices.len() - 1
+    }
+
+    pub fn device(&self, id: usize) -> &dyn Mem {
+        self.devices[id].device.as_ref()
+    }
+
+    pub fn device_mut(&mut self, id: usize) -> &mut dyn Mem {
+        self.devices[id].device.as_mut()
+    }
+
+    fn device_at(&self, addr: u16) -> Option<usize> {
+        self.devices
+            .iter()
+            .rposition(|attached| attached.range.contains(&addr))
+    }
+
+    pub fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    // fills the PRG RAM from a save file, if the cartridge has a battery and there is one
+    pub fn load_save(&mut self, path: &Path) -> io::Result<()> {
+        if self.rom.battery {
+            save::load(path, &mut self.prg_ram)?;
         }
+        Ok(())
+    }
+
+    // writes the PRG RAM out to a save file, if the cartridge has a battery
+    pub fn flush_save(&self, path: &Path) -> io::Result<()> {
+        if self.rom.battery {
+            save::store(path, &self.prg_ram)?;
+        }
+        Ok(())
+    }
+
+    // the DMA unit reads the page through the bus like the CPU would, and writes every byte
+    // to OAMDATA, so the copy starts at OAMADDR and wraps around
+    fn oam_dma(&mut self, page: u8) {
+        let start = (page as u16) << 8;
+        for i in 0..256 {
+            let data = self.mem_read(start + i);
+            self.ppu.write_register(0x2004, data);
+        }
+        self.dma.push_back(Dma::Oam);
+    }
+
+    // without a mapper to switch banks only the first 8KB can be seen, smaller RAM is mirrored
+    fn prg_ram_index(&self, addr: u16) -> usize {
+        (addr - PRG_RAM_START) as usize % self.prg_ram.len()
     }
 
     // the available space is 32KB but sometimes the PRG ROM's size is only 16 KB
@@ -38,7 +183,31 @@ impl Bus {
 }
 
 impl Mem for Bus {
-    fn mem_read(&self, addr: u16) -> u8 {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        if let Some(id) = self.device_at(addr) {
+            let data = self.devices[id].device.mem_read(addr);
+            self.open_bus = data;
+            return data;
+        }
+
+        let data = match addr {
+            PPU_REGISTERS_START..=PPU_REGISTERS_MIRROR_END => {
+                self.ppu.read_register(addr & PPU_REG_MASK)
+            }
+            APU_STATUS => self.apu.read_status() | (self.open_bus & APU_STATUS_OPEN_BUS),
+            JOYPAD1 => self.joypad1.read() | (self.open_bus & JOYPAD_OPEN_BUS),
+            JOYPAD2 => self.joypad2.read() | (self.open_bus & JOYPAD_OPEN_BUS),
+            _ => self.mem_peek(addr),
+        };
+        self.open_bus = data;
+        data
+    }
+
+    fn mem_peek(&self, addr: u16) -> u8 {
+        if let Some(id) = self.device_at(addr) {
+            return self.devices[id].device.mem_peek(addr);
+        }
+
         match addr {
             RAM_START..=RAM_MIRROR_END => {
                 // we are only keeping the lowest 11 bits here, aka masking the highest 2 bits. why?
@@ -53,14 +222,26 @@ impl Mem for Bus {
                 // works exactly like RAM, only difference is where it starts and ends, and
                 // which bits to hide -> 0x2000 - 0x2007
                 let mirrored = addr & PPU_REG_MASK;
-                todo!();
+                self.ppu.peek_register(mirrored)
             }
+            APU_STATUS => self.apu.peek_status() | (self.open_bus & APU_STATUS_OPEN_BUS),
+            JOYPAD1 => self.joypad1.peek() | (self.open_bus & JOYPAD_OPEN_BUS),
+            JOYPAD2 => self.joypad2.peek() | (self.open_bus & JOYPAD_OPEN_BUS),
+            // the rest of the APU registers and OAMDMA are write only
+            APU_REGISTERS_START..=OAM_DMA => self.open_bus,
+            PRG_RAM_START..=PRG_RAM_END => self.prg_ram[self.prg_ram_index(addr)],
             PRG_ROM_START..=PRG_ROM_END => self.read_prg_rom(addr),
-            _ => 0,
+            _ => self.open_bus,
         }
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
+        self.open_bus = data;
+        if let Some(id) = self.device_at(addr) {
+            self.devices[id].device.mem_write(addr, data);
+            return;
+        }
+
         match addr {
             RAM_START..=RAM_MIRROR_END => {
                 let mirrored = addr & RAM_MIRROR_MASK;
@@ -68,9 +249,371 @@ impl Mem for Bus {
             }
             PPU_REGISTERS_START..=PPU_REGISTERS_MIRROR_END => {
                 let mirrored = addr & PPU_REG_MASK;
-                todo!();
+                self.ppu.write_register(mirrored, data);
+            }
+            APU_REGISTERS_START..=APU_REGISTERS_END | APU_STATUS | JOYPAD2 => {
+                self.apu.write_register(addr, data)
+            }
+            OAM_DMA => self.oam_dma(data),
+            // the strobe goes out to both controller ports
+            JOYPAD1 => {
+                self.joypad1.write(data);
+                self.joypad2.write(data);
+            }
+            PRG_RAM_START..=PRG_RAM_END => {
+                let index = self.prg_ram_index(addr);
+                self.prg_ram[index] = data;
             }
             _ => {}
         }
     }
+
+    fn take_dma(&mut self) -> Option<Dma> {
+        self.dma.pop_front()
+    }
+
+    fn take_fault(&mut self) -> Option<u16> {
+        self.devices
+            .iter_mut()
+            .find_map(|attached| attached.device.take_fault())
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.ppu.nmi_line()
+            || self
+                .devices
+                .iter()
+                .any(|attached| attached.device.nmi_line())
+    }
+
+    // NROM has no IRQ source of its own, cartridge hardware that does can be attached
+    fn irq_line(&self) -> bool {
+        self.apu.irq_line()
+            || self
+                .devices
+                .iter()
+                .any(|attached| attached.device.irq_line())
+    }
+
+    // every CPU cycle is 3 PPU dots on NTSC and 3.2 on PAL, and one APU cycle
+    fn tick(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.dot_fifths += self.region.ppu_fifths_per_cpu_cycle();
+            self.ppu.tick(self.dot_fifths / 5);
+            self.dot_fifths %= 5;
+
+            self.apu.tick();
+            if let Some(addr) = self.apu.take_dmc_fetch() {
+                // there's no audio output yet, so the sample byte itself goes nowhere
+                self.mem_read(addr);
+                self.dma.push_back(Dma::Dmc);
+            }
+        }
+
+        for attached in &mut self.devices {
+            attached.device.tick(cycles);
+            while let Some(dma) = attached.device.take_dma() {
+                self.dma.push_back(dma);
+            }
+        }
+    }
+
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        Some((self.ppu.scanline(), self.ppu.dot()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apu::FrameCounter;
+    use crate::cpu::CPU;
+    use crate::flat_ram::FlatRam;
+    use crate::joypad::JoypadButton;
+    use crate::rom::test::{test_rom, test_rom_asm};
+
+    #[test]
+    fn test_ppu_registers_are_mirrored() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        // $3FFE -> $2006, $200F -> $2007
+        bus.mem_write(0x3ffe, 0x23);
+        bus.mem_write(0x3ffe, 0x05);
+        bus.mem_write(0x200f, 0x66);
+        assert_eq!(bus.ppu().vram[0x0305], 0x66);
+
+        bus.ppu_mut()
+            .status
+            .insert(crate::ppu::StatusRegister::VBLANK_STARTED);
+        assert_eq!(bus.mem_read(0x2ffa) & 0x80, 0x80); // $2002
+        assert_eq!(bus.mem_read(0x2002) & 0x80, 0);
+    }
+
+    #[test]
+    fn test_peeking_ppu_registers_has_no_side_effects() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        bus.mem_write(0x2006, 0x23);
+        bus.mem_write(0x2006, 0x05);
+        bus.mem_write(0x2007, 0x66);
+        bus.mem_write(0x2006, 0x23);
+        bus.mem_write(0x2006, 0x05);
+        bus.ppu_mut()
+            .status
+            .insert(crate::ppu::StatusRegister::VBLANK_STARTED);
+
+        assert_eq!(bus.mem_peek(0x2002) & 0x80, 0x80);
+        assert_eq!(bus.mem_peek(0x2002) & 0x80, 0x80);
+        assert_eq!(bus.mem_peek(0x2007), bus.mem_peek(0x2007));
+        assert_eq!(bus.ppu().vram_addr(), 0x2305);
+
+        // the reads still see everything the peeks left alone
+        assert_eq!(bus.mem_read(0x2002) & 0x80, 0x80);
+        bus.mem_read(0x2007); // fills the buffer
+        assert_eq!(bus.mem_peek(0x2007), 0x66);
+        assert_eq!(bus.mem_read(0x2007), 0x66);
+    }
+
+    #[test]
+    fn test_oam_dma_copies_a_page_from_oamaddr_on() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        for i in 0..256 {
+            bus.mem_write(0x0700 + i, i as u8);
+        }
+        bus.mem_write(0x2003, 0x10); // OAMADDR
+        assert_eq!(bus.take_dma(), None);
+
+        bus.mem_write(0x4014, 0x07);
+        assert_eq!(bus.take_dma(), Some(Dma::Oam));
+        assert_eq!(bus.take_dma(), None);
+        assert_eq!(bus.ppu().oam_data[0x10], 0x00);
+        assert_eq!(bus.ppu().oam_data[0xff], 0xef);
+        assert_eq!(bus.ppu().oam_data[0x00], 0xf0);
+        assert_eq!(bus.ppu().oam_addr, 0x10);
+    }
+
+    #[test]
+    fn test_apu_and_joypad_registers() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        bus.joypad2_mut().set_button(JoypadButton::B, true);
+
+        // $4017 writes go to the APU, reads come from the second controller
+        bus.mem_write(0x4017, 0b0100_0000);
+        assert_eq!(bus.apu().frame_counter, FrameCounter::IRQ_INHIBIT);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+        assert_eq!(bus.mem_read(0x4017), 0);
+        assert_eq!(bus.mem_peek(0x4017), 1);
+        assert_eq!(bus.mem_read(0x4017), 1);
+        assert_eq!(bus.mem_read(0x4016), 0);
+
+        bus.mem_write(0x4015, 0b0000_0001);
+        bus.mem_write(0x4003, 0b0000_1000);
+        assert_eq!(bus.apu().registers[0x03], 0b0000_1000);
+        assert_eq!(bus.mem_read(0x4015), 0b0000_0001);
+
+        // write only, the status read is still on the bus
+        assert_eq!(bus.mem_read(0x4003), 0b0000_0001);
+        assert_eq!(bus.mem_read(0x4014), 0b0000_0001);
+    }
+
+    #[test]
+    fn test_prg_ram() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        bus.mem_write(0x6000, 0x12);
+        bus.mem_write(0x7fff, 0x34);
+        assert_eq!(bus.mem_read(0x6000), 0x12);
+        assert_eq!(bus.mem_read(0x7fff), 0x34);
+        assert_eq!(bus.prg_ram().len(), 8192);
+    }
+
+    #[test]
+    fn test_save_only_with_a_battery() {
+        let path = std::env::temp_dir().join(format!("rustendo-{}-bus.sav", std::process::id()));
+
+        let mut bus = Bus::new(test_rom(vec![]));
+        bus.mem_write(0x6000, 0x12);
+        bus.flush_save(&path).unwrap();
+        assert!(!path.exists());
+
+        let mut rom = test_rom(vec![]);
+        rom.battery = true;
+        let mut bus = Bus::new(rom);
+        bus.mem_write(0x6000, 0x12);
+        bus.flush_save(&path).unwrap();
+
+        let mut rom = test_rom(vec![]);
+        rom.battery = true;
+        let mut bus = Bus::new(rom);
+        bus.load_save(&path).unwrap();
+        assert_eq!(bus.mem_read(0x6000), 0x12);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_unmapped_reads_return_open_bus() {
+        // LDA $5000 -> the last thing on the bus was the high byte of the operand
+        let mut cpu = CPU::new(Bus::new(test_rom_asm(
+            "
+                    LDA $5000
+                    LDX #$00
+                    LDA $4000,X
+            ",
+        )));
+        cpu.reset();
+        cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x50);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x40);
+
+        cpu.bus_mut().mem_write(0x0010, 0x99);
+        assert_eq!(cpu.bus().mem_peek(0x5000), 0x99);
+    }
+
+    #[test]
+    fn test_undriven_register_bits_come_from_open_bus() {
+        let mut cpu = CPU::new(Bus::new(test_rom_asm(
+            "
+                    LDA #$01
+                    STA $4016
+                    LDA #$00
+                    STA $4016
+                    LDA $4016
+                    LDX $4016
+            ",
+        )));
+        cpu.bus_mut()
+            .joypad1_mut()
+            .set_button(JoypadButton::A, true);
+        cpu.reset();
+        for _ in 0..6 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.register_a, 0x41);
+        assert_eq!(cpu.register_x, 0x40);
+
+        let mut bus = Bus::new(test_rom(vec![]));
+        bus.mem_write(0x0000, 0xff);
+        bus.mem_read(0x0000);
+        assert_eq!(bus.mem_read(0x4015), 0b0010_0000);
+    }
+
+    #[test]
+    fn test_ppu_runs_3_or_3_2_dots_per_cpu_cycle() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        bus.tick(10);
+        assert_eq!(bus.ppu().dot(), 30);
+
+        let mut bus = Bus::new(test_rom(vec![]));
+        bus.set_region(Region::Pal);
+        bus.tick(4);
+        assert_eq!(bus.ppu().dot(), 12);
+        bus.tick(1);
+        assert_eq!(bus.ppu().dot(), 16);
+    }
+
+    #[test]
+    fn test_vblank_nmi_reaches_the_cpu() {
+        let mut cpu = CPU::new(Bus::new(test_rom_asm(
+            "
+                    LDA #$80
+                    STA $2000
+            loop:   JMP loop
+            nmi:    INC $10
+                    RTI
+                    .org $fffa
+                    .word nmi
+            ",
+        )));
+        cpu.reset();
+        while cpu.bus().mem_peek(0x10) == 0 {
+            cpu.step().unwrap();
+            assert!(cpu.cycles < 30_000, "no NMI after a frame");
+        }
+
+        // vblank starts 241 * 341 + 1 dots after power on, the NMI is taken after the JMP it
+        // lands in
+        let vblank = (241 * 341 + 1) / 3;
+        let taken = cpu.cycles - 7 - 5; // the handler's INC and the NMI itself
+        assert!((vblank..vblank + 6).contains(&taken), "{}", taken);
+    }
+
+    // a homebrew style debug port: bytes written to it are collected, reading gives the count.
+    // it raises an IRQ after a few cycles, to see that it's ticked
+    struct DebugPort {
+        written: Vec<u8>,
+        ticks: usize,
+    }
+
+    impl Mem for DebugPort {
+        fn mem_peek(&self, _addr: u16) -> u8 {
+            self.written.len() as u8
+        }
+
+        fn mem_write(&mut self, _addr: u16, data: u8) {
+            self.written.push(data);
+        }
+
+        fn irq_line(&self) -> bool {
+            self.ticks >= 10
+        }
+
+        fn tick(&mut self, cycles: usize) {
+            self.ticks += cycles;
+        }
+    }
+
+    #[test]
+    fn test_attached_devices_take_over_their_range() {
+        let mut cpu = CPU::new(Bus::new(test_rom_asm(
+            "
+                    LDX #$00
+            loop:   LDA text,X
+                    BEQ done
+                    STA $6000
+                    INX
+                    JMP loop
+            done:   LDA $6000
+                    STA $10
+                    BRK
+            text:   .byte $48, $49, $00
+            ",
+        )));
+        let port = cpu.bus_mut().attach(
+            0x6000..=0x6000,
+            Box::new(DebugPort {
+                written: vec![],
+                ticks: 0,
+            }),
+        );
+        cpu.reset();
+        cpu.stop_on_brk = true;
+        cpu.run(|_| {}).unwrap();
+
+        assert_eq!(cpu.bus().mem_peek(0x10), 2);
+        assert_eq!(cpu.bus().device(port).mem_peek(0x6000), 2);
+        // the rest of PRG RAM is still there
+        assert_eq!(cpu.bus().prg_ram()[0], 0);
+        assert!(cpu.bus().irq_line());
+    }
+
+    #[test]
+    fn test_later_devices_win_where_they_overlap() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        let mut ram = FlatRam::new();
+        ram.mem_write(0x0000, 0x11);
+        bus.attach(0x0000..=0x00ff, Box::new(ram));
+        let mut ram = FlatRam::new();
+        ram.mem_write(0x0080, 0x22);
+        let top = bus.attach(0x0080..=0x00ff, Box::new(ram));
+
+        assert_eq!(bus.mem_read(0x0000), 0x11);
+        assert_eq!(bus.mem_read(0x0080), 0x22);
+        bus.mem_write(0x0090, 0x33);
+        assert_eq!(bus.device(top).mem_peek(0x0090), 0x33);
+        // the internal RAM under them isn't touched
+        assert_eq!(bus.cpu_vram[0x0090], 0);
+        bus.device_mut(top).mem_write(0x0100, 0x44);
+        assert_eq!(bus.mem_peek(0x0100), 0);
+    }
 }
@@ -2,96 +2,233 @@ use std::os;
 
 use crate::{
     addressing_mode::{self, AddressingMode},
-    bus::Bus,
+    cpu_error::CpuError,
+    dma::Dma,
     flags::Flags,
     mem::Mem,
-    opcodes::OPS_CODES_MAP,
+    opcodes::{self, OpCode},
     rom::Rom,
+    unstable_ops::UnstableOps,
+    variant::Variant,
 };
 
-pub struct CPU {
+pub struct StepResult {
+    pub opcode: u8,
+    pub cycles: usize, // includes servicing an interrupt raised during the instruction, and DMA stalls
+    pub halted: bool,  // hit BRK with `stop_on_brk` set, the BRK itself was not executed
+    pub jammed: bool,  // the CPU is locked up until the next reset
+}
+
+pub struct CPU<B> {
     pub program_counter: u16, // track the current position
     pub register_a: u8,       // accumulator
     pub register_x: u8,
     pub register_y: u8,
     pub status: Flags, // C Z I D B V
     pub stack_pointer: u8,
-    memory: [u8; 0xFFFF], // 65536
-    bus: Bus,
+    pub cycles: usize,     // total cycles elapsed since power up
+    pub stop_on_brk: bool, // return from `run` on BRK instead of jumping through $FFFE
+    pub variant: Variant,
+    pub unstable_ops: UnstableOps, // how ANE, LXA and the SH* stores behave
+    nmi_line: bool,
+    nmi_level: bool,   // the NMI input as of the last cycle, to spot the rising edge
+    nmi_pending: bool, // NMI is edge triggered, so we latch the rising edge until it's serviced
+    irq_line: bool,
+    irq_pending: bool,      // IRQ asserted and not masked as of the last cycle
+    interrupt_polled: bool, // what the poll on the second to last cycle saw
+    stall: usize,           // cycles left to sit out before the next instruction
+    jammed: bool,
+    waiting: bool, // 65C02 WAI, sleeping until an interrupt line goes active
+    bus: B,
 }
 
-impl Mem for CPU {
-    fn mem_read(&self, addr: u16) -> u8 {
+impl<B: Mem> Mem for CPU<B> {
+    fn mem_read(&mut self, addr: u16) -> u8 {
         self.bus.mem_read(addr)
     }
 
+    fn mem_peek(&self, addr: u16) -> u8 {
+        self.bus.mem_peek(addr)
+    }
+
     fn mem_write(&mut self, addr: u16, data: u8) {
         self.bus.mem_write(addr, data);
     }
 
-    fn mem_read_u16(&self, addr: u16) -> u16 {
+    fn mem_read_u16(&mut self, addr: u16) -> u16 {
         self.bus.mem_read_u16(addr)
     }
 
+    fn mem_peek_u16(&self, addr: u16) -> u16 {
+        self.bus.mem_peek_u16(addr)
+    }
+
     fn mem_write_u16(&mut self, addr: u16, data: u16) {
         self.bus.mem_write_u16(addr, data);
     }
 }
 
-impl CPU {
-    pub fn new(bus: Bus) -> Self {
+impl<B: Mem> CPU<B> {
+    pub fn new(bus: B) -> Self {
         CPU {
             program_counter: 0,
-            status: Flags::empty(),
+            status: Flags::INTERRUPT_DISABLE | Flags::BREAK2,
             register_a: 0,
             register_x: 0,
             register_y: 0,
             stack_pointer: 0xfd,
-            memory: [0; 0xFFFF],
+            cycles: 0,
+            stop_on_brk: false,
+            variant: Variant::Ricoh2A03,
+            unstable_ops: UnstableOps::default(),
+            nmi_line: false,
+            nmi_level: false,
+            nmi_pending: false,
+            irq_line: false,
+            irq_pending: false,
+            interrupt_polled: false,
+            stall: 0,
+            jammed: false,
+            waiting: false,
             bus,
         }
     }
 
-    fn get_operand_addr(&mut self, addressing_mode: AddressingMode) -> u16 {
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    // returns the operand address and whether indexing crossed a page boundary
+    fn get_operand_addr(&mut self, addressing_mode: AddressingMode) -> (u16, bool) {
+        self.resolve_operand(addressing_mode, false)
+    }
+
+    // stores and read-modify-writes can't know whether the indexed address was right before
+    // touching it, so they always pay for the dummy read
+    fn get_write_addr(&mut self, addressing_mode: AddressingMode) -> u16 {
+        self.resolve_operand(addressing_mode, true).0
+    }
+
+    // walks the addressing mode bus access by bus access, the way the chip does.
+    // unlike `get_effective_addr` this issues the dummy reads, so it's only for executing
+    fn resolve_operand(&mut self, addressing_mode: AddressingMode, store: bool) -> (u16, bool) {
+        let pc = self.program_counter;
         match addressing_mode {
-            AddressingMode::Immediate => self.program_counter,
-            _ => self.get_effective_addr(addressing_mode, self.program_counter),
+            AddressingMode::Immediate => (pc, false),
+            AddressingMode::ZeroPage => (self.read(pc) as u16, false),
+            AddressingMode::ZeroPage_X | AddressingMode::ZeroPage_Y => {
+                let base = self.read(pc);
+                let _ = self.read(base as u16); // read while the index gets added
+
+                let index = if matches!(addressing_mode, AddressingMode::ZeroPage_X) {
+                    self.register_x
+                } else {
+                    self.register_y
+                };
+                (base.wrapping_add(index) as u16, false)
+            }
+            AddressingMode::Absolute => (self.read_u16(pc), false),
+            AddressingMode::Absolute_X => {
+                let base = self.read_u16(pc);
+                let addr = base.wrapping_add(self.register_x as u16);
+                self.indexed_dummy_read(base, addr, pc.wrapping_add(1), store)
+            }
+            AddressingMode::Absolute_Y => {
+                let base = self.read_u16(pc);
+                let addr = base.wrapping_add(self.register_y as u16);
+                self.indexed_dummy_read(base, addr, pc.wrapping_add(1), store)
+            }
+            AddressingMode::Indirect_X => {
+                let base = self.read(pc);
+                let _ = self.read(base as u16);
+                let pointer = base.wrapping_add(self.register_x);
+
+                (self.read_zp_16(pointer as u16), false)
+            }
+            AddressingMode::Indirect_Y => {
+                let base = self.read(pc);
+                let pointer = self.read_zp_16(base as u16);
+                let addr = pointer.wrapping_add(self.register_y as u16);
+                self.indexed_dummy_read(pointer, addr, pc, store)
+            }
+            AddressingMode::Indirect_ZeroPage => {
+                let base = self.read(pc);
+                (self.read_zp_16(base as u16), false)
+            }
+            _ => (0, false),
         }
     }
 
-    pub fn get_effective_addr(&self, addressing_mode: AddressingMode, addr: u16) -> u16 {
+    // indexing only adds to the low byte first. the NMOS chip reads from that half-baked
+    // address and then again from the fixed one if the carry had to go into the high byte.
+    // the 65C02 re-reads the last operand byte instead
+    fn indexed_dummy_read(
+        &mut self,
+        base: u16,
+        addr: u16,
+        last_operand: u16,
+        store: bool,
+    ) -> (u16, bool) {
+        let page_crossed = page_crossed(base, addr);
+        if page_crossed || store {
+            let unfixed = if self.variant == Variant::Wdc65C02 {
+                last_operand
+            } else {
+                (base & 0xFF00) | (addr & 0x00FF)
+            };
+            let _ = self.read(unfixed);
+        }
+        (addr, page_crossed)
+    }
+
+    pub fn get_effective_addr(&self, addressing_mode: AddressingMode, addr: u16) -> (u16, bool) {
         match addressing_mode {
-            AddressingMode::Absolute => self.mem_read_u16(addr),
-            AddressingMode::ZeroPage => self.mem_read(addr) as u16,
+            AddressingMode::Absolute => (self.mem_peek_u16(addr), false),
+            AddressingMode::ZeroPage => (self.mem_peek(addr) as u16, false),
             AddressingMode::ZeroPage_X => {
-                let base = self.mem_read(addr);
-                base.wrapping_add(self.register_x) as u16
+                let base = self.mem_peek(addr);
+                (base.wrapping_add(self.register_x) as u16, false)
             }
             AddressingMode::ZeroPage_Y => {
-                let base = self.mem_read(addr);
-                base.wrapping_add(self.register_y) as u16
+                let base = self.mem_peek(addr);
+                (base.wrapping_add(self.register_y) as u16, false)
             }
             AddressingMode::Absolute_X => {
-                let base = self.mem_read_u16(addr);
-                base.wrapping_add(self.register_x as u16)
+                let base = self.mem_peek_u16(addr);
+                let addr = base.wrapping_add(self.register_x as u16);
+                (addr, page_crossed(base, addr))
             }
             AddressingMode::Absolute_Y => {
-                let base = self.mem_read_u16(addr);
-                base.wrapping_add(self.register_y as u16)
+                let base = self.mem_peek_u16(addr);
+                let addr = base.wrapping_add(self.register_y as u16);
+                (addr, page_crossed(base, addr))
             }
             AddressingMode::Indirect_X => {
-                let base = self.mem_read(addr);
+                let base = self.mem_peek(addr);
                 let pointer = base.wrapping_add(self.register_x);
 
-                self.read_zp_16(pointer as u16)
+                (self.peek_zp_16(pointer as u16), false)
             }
             AddressingMode::Indirect_Y => {
-                let base = self.mem_read(addr);
-                let pointer = self.read_zp_16(base as u16);
+                let base = self.mem_peek(addr);
+                let pointer = self.peek_zp_16(base as u16);
+                let addr = pointer.wrapping_add(self.register_y as u16);
 
-                pointer.wrapping_add(self.register_y as u16)
+                (addr, page_crossed(pointer, addr))
+            }
+            AddressingMode::Indirect_ZeroPage => {
+                let base = self.mem_peek(addr);
+                (self.peek_zp_16(base as u16), false)
             }
-            _ => 0,
+            _ => (0, false),
         }
     }
 
@@ -99,54 +236,72 @@ impl CPU {
         self.register_a = 0;
         self.register_x = 0;
         self.register_y = 0;
-        self.status = Flags::empty();
+        self.stack_pointer = 0xfd;
+        self.jammed = false;
+        self.waiting = false;
+        self.nmi_pending = false;
+        self.irq_pending = false;
+        self.interrupt_polled = false;
+        self.stall = 0;
+        self.status = Flags::INTERRUPT_DISABLE | Flags::BREAK2;
+        // the reset sequence itself takes 7 cycles before the first instruction is fetched,
+        // the last 2 read the vector
+        self.cycles = 7;
+        for _ in 0..5 {
+            self.end_cycle();
+        }
 
         // NES stores the 2 bytes starting memory addr at 0xFFFC
-        self.program_counter = self.mem_read_u16(0xFFFC);
+        self.program_counter = self.read_u16(0xFFFC);
     }
 
     pub fn load(&mut self, program: &[u8]) {
-        // why 0x8000 you ask?
-        // because PRG-ROM, the address where the NES program is going to be mapped, starts from
-        // 0x8000 to 0xFFFF. the other addresses are reserved for other things
-        self.memory[0x0600..(0x0600 + program.len())].copy_from_slice(&program[..]);
+        // why 0x0600 you ask?
+        // that's where easy6502 style programs (like the snake game) expect to be loaded.
+        // this only makes sense on a writable bus, on the NES the program lives in PRG-ROM instead
+        for (i, byte) in program.iter().enumerate() {
+            self.mem_write(0x0600 + i as u16, *byte);
+        }
         self.mem_write_u16(0xFFFC, 0x0600);
     }
 
     fn lda(&mut self, addresing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addresing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addresing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
         self.register_a = value;
         self.update_zero_and_negative_flag(self.register_a);
     }
 
     fn ldy(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
         self.register_y = value;
         self.update_zero_and_negative_flag(value);
     }
 
     fn ldx(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
         self.register_x = value;
         self.update_zero_and_negative_flag(value);
     }
 
     fn sta(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        self.mem_write(addr, self.register_a);
+        let addr = self.get_write_addr(addressing_mode);
+        self.write(addr, self.register_a);
     }
 
     fn stx(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        self.mem_write(addr, self.register_x);
+        let addr = self.get_write_addr(addressing_mode);
+        self.write(addr, self.register_x);
     }
 
     fn sty(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        self.mem_write(addr, self.register_y);
+        let addr = self.get_write_addr(addressing_mode);
+        self.write(addr, self.register_y);
     }
 
     fn tax(&mut self) {
@@ -190,29 +345,33 @@ impl CPU {
     }
 
     fn and(&mut self, addresing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addresing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addresing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
         self.register_a = self.register_a & value;
         self.update_zero_and_negative_flag(self.register_a);
     }
 
     fn ora(&mut self, addresing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addresing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addresing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
         self.register_a = self.register_a | value;
         self.update_zero_and_negative_flag(self.register_a);
     }
 
     fn eor(&mut self, addresing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addresing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addresing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
         self.register_a = self.register_a ^ value;
         self.update_zero_and_negative_flag(self.register_a);
     }
 
     fn bit(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
         let and = value & self.register_a;
 
         if and == 0 {
@@ -226,8 +385,9 @@ impl CPU {
     }
 
     fn cmp(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
 
         if self.register_a >= value {
             self.status.insert(Flags::CARRY);
@@ -240,8 +400,8 @@ impl CPU {
     }
 
     fn cpx(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
 
         if self.register_x >= value {
             self.status.insert(Flags::CARRY);
@@ -254,8 +414,8 @@ impl CPU {
     }
 
     fn cpy(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
 
         if self.register_y >= value {
             self.status.insert(Flags::CARRY);
@@ -268,22 +428,42 @@ impl CPU {
     }
 
     fn adc(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
-        self.add_to_register_a(value);
+        let (addr, page_crossed) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
+        self.add_with_carry(value);
     }
 
     fn sbc(&mut self, addresing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addresing_mode);
-        let value = self.mem_read(addr);
-        self.add_to_register_a(!value);
+        let (addr, page_crossed) = self.get_operand_addr(addresing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
+        self.subtract_with_borrow(value);
     }
 
     fn branch(&mut self, condition: bool) {
+        let value = self.read(self.program_counter) as i8; // branch expects a signed byte
         if condition {
-            let value = self.mem_read(self.program_counter) as i8; // branch expects a signed byte
-            self.program_counter += 1; // consume operand
+            // a taken branch costs 1 extra cycle, and another one if it lands on a different page
+            self.cycles += 1;
+
+            self.program_counter = self.program_counter.wrapping_add(1); // consume operand
             let jump_addr = self.program_counter.wrapping_add(value as i16 as u16);
+
+            // the next opcode gets fetched and thrown away while the offset is added. without
+            // a page cross that cycle doesn't poll, so an interrupt showing up during it waits
+            // until after the next instruction
+            let polled = self.interrupt_polled;
+            let _ = self.read(self.program_counter);
+
+            if page_crossed(self.program_counter, jump_addr) {
+                self.cycles += 1;
+                // same for the target before its high byte is fixed up
+                let _ = self.read((self.program_counter & 0xFF00) | (jump_addr & 0x00FF));
+            } else {
+                self.interrupt_polled = polled;
+            }
+
             self.program_counter = jump_addr;
         }
     }
@@ -297,8 +477,9 @@ impl CPU {
     }
 
     fn asl(&mut self, addressing_mode: AddressingMode) -> u8 {
-        let addr = self.get_operand_addr(addressing_mode);
-        let mut value = self.mem_read(addr);
+        let addr = self.get_shift_addr(addressing_mode);
+        let old = self.read(addr);
+        let mut value = old;
 
         // get bit 7
         let carry = (value >> 7) & 1;
@@ -306,7 +487,7 @@ impl CPU {
 
         self.status.set(Flags::CARRY, carry == 1);
 
-        self.mem_write(addr, value);
+        self.write_modified(addr, old, value);
         self.update_zero_and_negative_flag(value);
 
         value
@@ -321,8 +502,9 @@ impl CPU {
     }
 
     fn lsr(&mut self, addressing_mode: AddressingMode) -> u8 {
-        let addr = self.get_operand_addr(addressing_mode);
-        let mut value = self.mem_read(addr);
+        let addr = self.get_shift_addr(addressing_mode);
+        let old = self.read(addr);
+        let mut value = old;
 
         // get bit 0
         let carry = value & 0x01;
@@ -330,7 +512,7 @@ impl CPU {
 
         value >>= 1;
 
-        self.mem_write(addr, value);
+        self.write_modified(addr, old, value);
         self.update_zero_and_negative_flag(value);
 
         value
@@ -352,8 +534,9 @@ impl CPU {
     }
 
     fn rol(&mut self, addressing_mode: AddressingMode) -> u8 {
-        let addr = self.get_operand_addr(addressing_mode);
-        let mut value = self.mem_read(addr);
+        let addr = self.get_shift_addr(addressing_mode);
+        let old = self.read(addr);
+        let mut value = old;
 
         let old_carry = if self.status.contains(Flags::CARRY) {
             1
@@ -366,7 +549,7 @@ impl CPU {
 
         value <<= 1;
         value |= old_carry;
-        self.mem_write(addr, value);
+        self.write_modified(addr, old, value);
         self.update_zero_and_negative_flag(value);
 
         value
@@ -388,8 +571,9 @@ impl CPU {
     }
 
     fn ror(&mut self, addressing_mode: AddressingMode) -> u8 {
-        let addr = self.get_operand_addr(addressing_mode);
-        let mut value = self.mem_read(addr);
+        let addr = self.get_shift_addr(addressing_mode);
+        let old = self.read(addr);
+        let mut value = old;
 
         let old_carry = if self.status.contains(Flags::CARRY) {
             1
@@ -402,37 +586,53 @@ impl CPU {
 
         value >>= 1;
         value |= old_carry << 7;
-        self.mem_write(addr, value);
+        self.write_modified(addr, old, value);
         self.update_zero_and_negative_flag(value);
 
         value
     }
 
     fn jmp_absolute(&mut self) {
-        let addr = self.mem_read_u16(self.program_counter);
+        let addr = self.read_u16(self.program_counter);
         self.program_counter = addr;
     }
 
     fn jmp_indirect(&mut self) {
-        let addr = self.mem_read_u16(self.program_counter);
+        let addr = self.read_u16(self.program_counter);
 
-        // 6502 has a bug that we have to mimic
-        let indirect_mem = if addr & 0x00FF == 0x00FF {
+        // 6502 has a bug that we have to mimic. the 65C02 fixed it, and pays a cycle for it
+        if self.variant == Variant::Wdc65C02 {
+            let _ = self.read(self.program_counter.wrapping_add(1));
+        }
+        let indirect_mem = if addr & 0x00FF == 0x00FF && self.variant != Variant::Wdc65C02 {
             // so the idea is, if the low byte equals to 0xFF, which is at the page boundary,
             // a carry should be added to the high byte, right? e.g 9 + 7 -> carry = 1
             // but we don't want that, instead we use the original high byte, hence the bit masking
-            let low = self.mem_read(addr);
-            let high = self.mem_read(addr & 0xFF00); // get original high byte
+            let low = self.read(addr);
+            let high = self.read(addr & 0xFF00); // get original high byte
             (high as u16) << 8 | low as u16
         } else {
-            self.mem_read_u16(addr)
+            self.read_u16(addr)
         };
 
         self.program_counter = indirect_mem;
     }
 
+    // 65C02 JMP (abs,X)
+    fn jmp_indexed_indirect(&mut self) {
+        let base = self.read_u16(self.program_counter);
+        let _ = self.read(self.program_counter.wrapping_add(1));
+        let addr = base.wrapping_add(self.register_x as u16);
+        self.program_counter = self.read_u16(addr);
+    }
+
     fn jsr(&mut self) {
-        let return_addr = self.program_counter + 2 - 1; // as stated in the 6502 instructions
+        let return_addr = self.program_counter.wrapping_add(2 - 1); // as stated in the 6502 instructions
+
+        // the high byte of the target is only fetched after the return address is pushed,
+        // which matters when the stack runs over the instruction itself
+        let target_low = self.read(self.program_counter) as u16;
+        self.stack_dummy_read();
 
         let high = (return_addr >> 8) as u8;
         let low = (return_addr & 0xff) as u8;
@@ -440,16 +640,18 @@ impl CPU {
         self.stack_push(high);
         self.stack_push(low);
 
-        let target_addr = self.mem_read_u16(self.program_counter);
+        let target_high = self.read(self.program_counter.wrapping_add(1)) as u16;
 
-        self.program_counter = target_addr;
+        self.program_counter = target_high << 8 | target_low;
     }
 
     fn rts(&mut self) {
+        self.stack_dummy_read();
         let low = self.stack_pop() as u16;
         let high = self.stack_pop() as u16;
 
         let return_addr = high << 8 | low;
+        let _ = self.read(return_addr); // one more cycle to increment it
 
         self.program_counter = return_addr.wrapping_add(1);
     }
@@ -459,6 +661,7 @@ impl CPU {
     }
 
     fn pla(&mut self) {
+        self.stack_dummy_read();
         let value = self.stack_pop();
         self.register_a = value;
         self.update_zero_and_negative_flag(self.register_a);
@@ -471,6 +674,7 @@ impl CPU {
     }
 
     fn plp(&mut self) {
+        self.stack_dummy_read();
         self.restore_status_from_stack();
     }
 
@@ -503,20 +707,35 @@ impl CPU {
     }
 
     fn dec(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr).wrapping_sub(1);
-        self.mem_write(addr, value);
+        let addr = self.get_write_addr(addressing_mode);
+        let old = self.read(addr);
+        let value = old.wrapping_sub(1);
+        self.write_modified(addr, old, value);
         self.update_zero_and_negative_flag(value);
     }
 
     fn inc(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr).wrapping_add(1);
-        self.mem_write(addr, value);
+        let addr = self.get_write_addr(addressing_mode);
+        let old = self.read(addr);
+        let value = old.wrapping_add(1);
+        self.write_modified(addr, old, value);
         self.update_zero_and_negative_flag(value);
     }
 
+    fn brk(&mut self) {
+        // BRK is a 2 byte instruction, the byte after the opcode is just padding
+        self.interrupt(BRK);
+    }
+
+    fn jam(&mut self, pc: u16) {
+        // the real chip gets stuck fetching forever, keep the program counter on the
+        // offending opcode so traces and crash reports point at it
+        self.jammed = true;
+        self.program_counter = pc;
+    }
+
     fn rti(&mut self) {
+        self.stack_dummy_read();
         self.restore_status_from_stack();
 
         let low = self.stack_pop() as u16;
@@ -535,8 +754,8 @@ impl CPU {
 
     // unofficial instructions
     fn aac_anc(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
 
         // AND byte with accumulator
         self.register_a &= value;
@@ -550,15 +769,15 @@ impl CPU {
     }
 
     fn aax_sax_axs(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
+        let addr = self.get_write_addr(addressing_mode);
         let res = self.register_x & self.register_a;
-        self.mem_write(addr, res);
+        self.write(addr, res);
         self.update_zero_and_negative_flag(res);
     }
 
     fn arr(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
 
         self.register_a &= value;
         self.update_zero_and_negative_flag(self.register_a);
@@ -585,31 +804,28 @@ impl CPU {
     }
 
     fn asr_alr(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
 
         self.register_a &= value;
         self.lsr_accumulator();
     }
 
     fn atx_lxa_oal(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
 
-        self.register_a &= value;
+        self.register_a = (self.register_a | self.unstable_ops.lxa_magic) & value;
         self.tax();
     }
 
     fn axa_sha(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-
-        let data = self.register_x & self.register_a & (addr >> 7) as u8;
-        self.mem_write(addr, data);
+        self.store_and_high_byte(addressing_mode, self.register_a & self.register_x);
     }
 
     fn axs_sbx_sax(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
 
         let ax = self.register_x & self.register_a;
         let result = self.register_x.wrapping_sub(value);
@@ -627,11 +843,11 @@ impl CPU {
     }
 
     fn dcp_dcm(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let mut value = self.mem_read(addr);
+        let addr = self.get_write_addr(addressing_mode);
+        let old = self.read(addr);
 
-        value = value.wrapping_sub(1);
-        self.mem_write(addr, value);
+        let value = old.wrapping_sub(1);
+        self.write_modified(addr, old, value);
 
         let result = self.register_a.wrapping_sub(value);
 
@@ -645,13 +861,18 @@ impl CPU {
     }
 
     fn isc_isb_ins(&mut self, addressing_mode: AddressingMode) {
-        self.inc(addressing_mode);
-        self.sbc(addressing_mode);
+        // same as INC followed by SBC, but without fetching the operand twice
+        let addr = self.get_write_addr(addressing_mode);
+        let old = self.read(addr);
+        let value = old.wrapping_add(1);
+        self.write_modified(addr, old, value);
+        self.subtract_with_borrow(value);
     }
 
     fn lar_lax(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
 
         self.register_a = value;
         self.update_zero_and_negative_flag(value);
@@ -667,7 +888,7 @@ impl CPU {
 
     fn rra(&mut self, addressing_mode: AddressingMode) {
         let data = self.ror(addressing_mode);
-        self.add_to_register_a(data);
+        self.add_with_carry(data);
     }
 
     fn slo_aso(&mut self, addressing_mode: AddressingMode) {
@@ -683,39 +904,52 @@ impl CPU {
     }
 
     fn sxa_shx_xas(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let result = self.register_x & ((addr >> 8) as u8 + 1);
-        self.mem_write(addr, result);
+        self.store_and_high_byte(addressing_mode, self.register_x);
     }
 
     fn sya_shy_say(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let result = self.register_y & ((addr >> 8) as u8 + 1);
-        self.mem_write(addr, result);
+        self.store_and_high_byte(addressing_mode, self.register_y);
     }
 
     fn xaa_ane(&mut self, addressing_mode: AddressingMode) {
-        self.register_a = self.register_x;
-        self.update_zero_and_negative_flag(self.register_a);
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, _) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
 
-        self.register_a &= value;
+        self.register_a = (self.register_a | self.unstable_ops.ane_magic) & self.register_x & value;
         self.update_zero_and_negative_flag(self.register_a);
     }
 
     fn xas_shs_tas(&mut self, addressing_mode: AddressingMode) {
-        let data = self.register_x & self.register_a;
-        self.stack_pointer = data;
+        self.stack_pointer = self.register_x & self.register_a;
+        self.store_and_high_byte(addressing_mode, self.stack_pointer);
+    }
+
+    // the SH* stores AND the value with the high byte of the unindexed address plus one.
+    // the index is subtracted back out instead of re-reading the operand, that would show up
+    // on the bus
+    fn store_and_high_byte(&mut self, addressing_mode: AddressingMode, value: u8) {
+        let addr = self.get_write_addr(addressing_mode);
+        let index = match addressing_mode {
+            AddressingMode::Absolute_X => self.register_x,
+            _ => self.register_y,
+        };
+        let base = addr.wrapping_sub(index as u16);
 
-        let addr = self.get_operand_addr(addressing_mode);
-        let result = self.stack_pointer & ((addr >> 8) as u8 + 1);
-        self.mem_write(addr, result);
+        let data = value & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if page_crossed(base, addr)
+            && self.unstable_ops.sh_page_cross_uses_value_as_high_byte
+        {
+            ((data as u16) << 8) | (addr & 0x00ff)
+        } else {
+            addr
+        };
+        self.write(addr, data);
     }
 
     fn lar_lae_las(&mut self, addressing_mode: AddressingMode) {
-        let addr = self.get_operand_addr(addressing_mode);
-        let value = self.mem_read(addr);
+        let (addr, page_crossed) = self.get_operand_addr(addressing_mode);
+        let value = self.read(addr);
+        self.add_page_cross_penalty(page_crossed);
 
         let result = self.stack_pointer & value;
 
@@ -726,7 +960,253 @@ impl CPU {
         self.update_zero_and_negative_flag(result);
     }
 
+    // halts the CPU for `cycles` cycles, the way pulling RDY low does. they're spent before
+    // the next instruction, or before an interrupt that's about to be serviced
+    pub fn stall(&mut self, cycles: usize) {
+        self.stall += cycles;
+    }
+
+    pub fn start_dma(&mut self, dma: Dma) {
+        let cycles = match dma {
+            // the extra alignment cycle comes in when the DMA starts on an odd cycle
+            Dma::Oam => 513 + self.cycles % 2,
+            Dma::Dmc if self.stall > 0 => 2,
+            Dma::Dmc => 4,
+        };
+        self.stall(cycles);
+    }
+
+    // the bus keeps running while the CPU is halted, so interrupts raised meanwhile still get
+    // latched and are serviced after the next instruction. whether one gets serviced right
+    // after the stall was decided by the poll before it. DMAs the bus starts meanwhile join
+    // the stall, so a DMC fetch during an OAM DMA is the cheap kind
+    fn spend_stall(&mut self) {
+        let polled = self.interrupt_polled;
+        loop {
+            while let Some(dma) = self.bus.take_dma() {
+                self.start_dma(dma);
+            }
+            if self.stall == 0 {
+                break;
+            }
+            self.stall -= 1;
+            self.end_cycle();
+            self.cycles += 1;
+        }
+        self.interrupt_polled = polled;
+    }
+
+    // drives the NMI input. only the transition from inactive to active raises an interrupt,
+    // and only if the line is still active at the end of a cycle. devices on the bus can
+    // drive it too, through `Mem::nmi_line`
+    pub fn set_nmi_line(&mut self, active: bool) {
+        self.nmi_line = active;
+    }
+
+    // drives the IRQ input. it keeps firing for as long as it's held active and the
+    // interrupt disable flag is clear, so devices have to release it once acknowledged
+    pub fn set_irq_line(&mut self, active: bool) {
+        self.irq_line = active;
+    }
+
+    // CLI, SEI and PLP change the I flag on their last cycle, after the poll, so their effect
+    // on IRQs only shows one instruction later. RTI restores it before the poll
+    fn poll_interrupts(&mut self) {
+        if self.interrupt_polled {
+            self.interrupt(if self.nmi_pending { NMI } else { IRQ });
+            self.cycles += 7;
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.irq_line || self.bus.irq_line()
+    }
+
+    fn interrupt(&mut self, interrupt: Interrupt) {
+        if interrupt.kind != InterruptKind::Brk {
+            // a hardware interrupt replaces the next opcode fetch, which still happens twice
+            let _ = self.read(self.program_counter);
+            let _ = self.read(self.program_counter);
+        }
+
+        let return_addr = match interrupt.kind {
+            // the program counter is already past the opcode, skip the padding byte too
+            InterruptKind::Brk => self.program_counter.wrapping_add(1),
+            _ => self.program_counter,
+        };
+        self.stack_push((return_addr >> 8) as u8);
+        self.stack_push((return_addr & 0xff) as u8);
+
+        // the vector is only picked after the return address is pushed. an NMI that shows up
+        // by then takes over the sequence, even for BRK, which keeps its B flag on the stack
+        let vector_addr = if self.nmi_pending {
+            self.nmi_pending = false;
+            NMI.vector_addr
+        } else {
+            interrupt.vector_addr
+        };
+
+        // bit 5 is always pushed as 1, bit 4 tells the handler whether it came from BRK
+        let mut status = self.status.bits() | 0b0010_0000;
+        if interrupt.kind == InterruptKind::Brk {
+            status |= 0b0001_0000;
+        } else {
+            status &= !0b0001_0000;
+        }
+        self.stack_push(status);
+        self.status.insert(Flags::INTERRUPT_DISABLE);
+        if self.variant == Variant::Wdc65C02 {
+            self.status.remove(Flags::DECIMAL_MODE);
+        }
+
+        self.program_counter = self.read_u16(vector_addr);
+        // the first instruction of the handler always runs before the next interrupt
+        self.interrupt_polled = false;
+    }
+
+    // 65C02 instructions
+    fn plx(&mut self) {
+        self.stack_dummy_read();
+        self.register_x = self.stack_pop();
+        self.update_zero_and_negative_flag(self.register_x);
+    }
+
+    fn ply(&mut self) {
+        self.stack_dummy_read();
+        self.register_y = self.stack_pop();
+        self.update_zero_and_negative_flag(self.register_y);
+    }
+
+    fn stz(&mut self, addressing_mode: AddressingMode) {
+        let addr = self.get_write_addr(addressing_mode);
+        self.write(addr, 0);
+    }
+
+    fn tsb(&mut self, addressing_mode: AddressingMode) {
+        let addr = self.get_write_addr(addressing_mode);
+        let value = self.read(addr);
+
+        self.status.set(Flags::ZERO, value & self.register_a == 0);
+        self.write_modified(addr, value, value | self.register_a);
+    }
+
+    fn trb(&mut self, addressing_mode: AddressingMode) {
+        let addr = self.get_write_addr(addressing_mode);
+        let value = self.read(addr);
+
+        self.status.set(Flags::ZERO, value & self.register_a == 0);
+        self.write_modified(addr, value, value & !self.register_a);
+    }
+
+    fn inc_accumulator(&mut self) {
+        self.register_a = self.register_a.wrapping_add(1);
+        self.update_zero_and_negative_flag(self.register_a);
+    }
+
+    fn dec_accumulator(&mut self) {
+        self.register_a = self.register_a.wrapping_sub(1);
+        self.update_zero_and_negative_flag(self.register_a);
+    }
+
+    // unlike the other BIT modes, the immediate one only touches Z
+    fn bit_immediate(&mut self) {
+        let value = self.read(self.program_counter);
+        self.status.set(Flags::ZERO, value & self.register_a == 0);
+    }
+
+    // RMBn and SMBn, bit 7 of the opcode picks set or reset, bits 4-6 pick the bit
+    fn rmb_smb(&mut self, code: u8) {
+        let mask = 1 << ((code >> 4) & 0b111);
+        let (addr, _) = self.get_operand_addr(AddressingMode::ZeroPage);
+        let value = self.read(addr);
+
+        let result = if code & 0x80 != 0 {
+            value | mask
+        } else {
+            value & !mask
+        };
+        self.write_modified(addr, value, result);
+    }
+
+    // BBRn and BBSn, same opcode layout as RMB/SMB. operands are a zero page address
+    // and then a relative branch offset
+    fn bbr_bbs(&mut self, code: u8) {
+        let mask = 1 << ((code >> 4) & 0b111);
+        let (addr, _) = self.get_operand_addr(AddressingMode::ZeroPage);
+        let value = self.read(addr);
+        let _ = self.read(addr); // the value gets read twice
+        self.program_counter = self.program_counter.wrapping_add(1); // consume the zero page operand
+
+        let bit_set = value & mask != 0;
+        let branch_if_set = code & 0x80 != 0;
+        if bit_set == branch_if_set {
+            self.branch(true);
+        } else {
+            // the offset is fetched either way
+            let _ = self.read(self.program_counter);
+            self.program_counter = self.program_counter.wrapping_add(1); // consume the offset
+        }
+    }
+
+    fn wai(&mut self) {
+        let _ = self.read(self.program_counter);
+        // an interrupt that's already asserted falls straight through
+        self.waiting = !self.nmi_pending && !self.irq_asserted();
+    }
+
+    // defined NOPs still fetch their operand like the instruction they replace
+    fn nop_65c02(&mut self, addressing_mode: AddressingMode) {
+        match addressing_mode {
+            AddressingMode::Immediate => {
+                let _data = self.read(self.program_counter);
+            }
+            AddressingMode::ZeroPage | AddressingMode::ZeroPage_X | AddressingMode::Absolute => {
+                let (addr, _) = self.get_operand_addr(addressing_mode);
+                let _data = self.read(addr);
+            }
+            _ => {}
+        }
+    }
+
+    // $5C is the odd one out, it spends 8 cycles reading from $FFxx
+    fn nop_5c(&mut self) {
+        let low = self.read(self.program_counter);
+        let _ = self.read(self.program_counter.wrapping_add(1));
+        for _ in 0..5 {
+            let _ = self.read(0xFF00 | low as u16);
+        }
+    }
+
     // helper functions
+    fn get_shift_addr(&mut self, addressing_mode: AddressingMode) -> u16 {
+        // the NMOS chip always spends the extra cycle on read-modify-write abs,X,
+        // the 65C02 only does for shifts and rotates when indexing crosses a page
+        if self.variant == Variant::Wdc65C02 {
+            let (addr, page_crossed) = self.get_operand_addr(addressing_mode);
+            self.add_page_cross_penalty(page_crossed);
+            addr
+        } else {
+            self.get_write_addr(addressing_mode)
+        }
+    }
+
+    // read-modify-write instructions spend a cycle on the modify step. the NMOS chip writes
+    // the unmodified value back during it, the 65C02 reads it again instead
+    fn write_modified(&mut self, addr: u16, old: u8, new: u8) {
+        if self.variant == Variant::Wdc65C02 {
+            let _ = self.read(addr);
+        } else {
+            self.write(addr, old);
+        }
+        self.write(addr, new);
+    }
+
+    fn add_page_cross_penalty(&mut self, page_crossed: bool) {
+        if page_crossed {
+            self.cycles += 1;
+        }
+    }
+
     fn restore_status_from_stack(&mut self) {
         let mut value = self.stack_pop();
         // make sure bit 5 stays 1
@@ -743,14 +1223,122 @@ impl CPU {
     // weird ik
     fn stack_push(&mut self, data: u8) {
         // 0x0100 is the starting point of the stack in the NES CPU memory map
-        self.mem_write(0x0100 + self.stack_pointer as u16, data);
+        self.write(0x0100 + self.stack_pointer as u16, data);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
+    // pulls spend a cycle incrementing the stack pointer, reading whatever it points at meanwhile
+    fn stack_dummy_read(&mut self) {
+        let _ = self.read(0x0100 + self.stack_pointer as u16);
+    }
+
     fn stack_pop(&mut self) -> u8 {
         // the pointer points to the next empty position, so that's why we decrement it first
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        self.mem_read(0x0100 + self.stack_pointer as u16) as u8
+        self.read(0x0100 + self.stack_pointer as u16)
+    }
+
+    // ADC. everything that adds to A goes through here so decimal mode is handled in one place
+    fn add_with_carry(&mut self, value: u8) {
+        if self.status.contains(Flags::DECIMAL_MODE) && self.variant.has_decimal_mode() {
+            self.add_cmos_decimal_penalty();
+            self.decimal_add(value);
+        } else {
+            self.add_to_register_a(value);
+        }
+    }
+
+    // SBC. in binary mode subtracting is just adding the one's complement
+    fn subtract_with_borrow(&mut self, value: u8) {
+        if self.status.contains(Flags::DECIMAL_MODE) && self.variant.has_decimal_mode() {
+            self.add_cmos_decimal_penalty();
+            self.decimal_subtract(value);
+        } else {
+            self.add_to_register_a(!value);
+        }
+    }
+
+    // the 65C02 spends one more cycle fixing up the flags in decimal mode
+    fn add_cmos_decimal_penalty(&mut self) {
+        if self.variant == Variant::Wdc65C02 {
+            let _ = self.read(self.program_counter);
+            self.cycles += 1;
+        }
+    }
+
+    // BCD addition. the carry and the result are proper BCD, but on NMOS the flags are quirky:
+    // Z comes from the plain binary sum, while N and V come from the sum after only the low
+    // nibble got adjusted. the 65C02 sets N and Z from the result, V stays the same.
+    // see http://www.6502.org/tutorials/decimal_mode.html
+    fn decimal_add(&mut self, value: u8) {
+        let a = self.register_a;
+        let carry = self.status.contains(Flags::CARRY) as i16;
+
+        let binary = a.wrapping_add(value).wrapping_add(carry as u8);
+
+        let mut low = (a & 0x0F) as i16 + (value & 0x0F) as i16 + carry;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+
+        // N and V look at the intermediate sum as a signed number
+        let signed = (a & 0xF0) as i8 as i16 + (value & 0xF0) as i8 as i16 + low;
+        let mut sum = (a & 0xF0) as i16 + (value & 0xF0) as i16 + low;
+
+        self.status.set(Flags::NEGATIVE, sum & 0x80 != 0);
+        self.status
+            .set(Flags::OVERFLOW, !(-128..=127).contains(&signed));
+
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+
+        self.status.set(Flags::CARRY, sum >= 0x100);
+        self.status.set(Flags::ZERO, binary == 0);
+        self.register_a = sum as u8;
+
+        if self.variant == Variant::Wdc65C02 {
+            self.update_zero_and_negative_flag(self.register_a);
+        }
+    }
+
+    // BCD subtraction. on NMOS all the flags are the same as in binary mode and only the
+    // value that lands in A is adjusted. the 65C02 adjusts differently, and sets N and Z
+    // from the result
+    fn decimal_subtract(&mut self, value: u8) {
+        let a = self.register_a;
+        let carry = self.status.contains(Flags::CARRY) as i16;
+
+        let low = (a & 0x0F) as i16 - (value & 0x0F) as i16 + carry - 1;
+
+        let result = if self.variant == Variant::Wdc65C02 {
+            let mut result = a as i16 - value as i16 + carry - 1;
+            if result < 0 {
+                result -= 0x60;
+            }
+            if low < 0 {
+                result -= 0x06;
+            }
+            result
+        } else {
+            let low = if low < 0 {
+                ((low - 0x06) & 0x0F) - 0x10
+            } else {
+                low
+            };
+            let mut result = (a & 0xF0) as i16 - (value & 0xF0) as i16 + low;
+            if result < 0 {
+                result -= 0x60;
+            }
+            result
+        };
+
+        self.add_to_register_a(!value);
+        self.register_a = result as u8;
+
+        if self.variant == Variant::Wdc65C02 {
+            self.update_zero_and_negative_flag(self.register_a);
+        }
     }
 
     fn add_to_register_a(&mut self, value: u8) {
@@ -793,277 +1381,1638 @@ impl CPU {
     //  LDA $8000 <=> A9 00 80
     //  since NES uses little endian, the CPU will read 0x00 (least significant) first then 0x80 (most significant)
     //  since people write numbers from the most significant part first, we get 0x8000
-    fn mem_read_u16(&mut self, addr: u16) -> u16 {
-        let low = self.mem_read(addr) as u16;
-        let high = self.mem_read(addr + 1) as u16;
+    fn read_u16(&mut self, addr: u16) -> u16 {
+        let low = self.read(addr) as u16;
+        let high = self.read(addr.wrapping_add(1)) as u16;
         (high << 8) | low
     }
 
-    fn mem_write_u16(&mut self, addr: u16, data: u16) {
-        let low = (data & 0xff) as u8; // mask everything except the low part
-        let high = (data >> 8) as u8;
+    // every access the CPU makes while executing goes through `read` and `write`, one per cycle.
+    // the `Mem` impl above is for everybody else and doesn't count as a cycle
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.bus.mem_read(addr);
+        self.end_cycle();
+        data
+    }
 
-        self.mem_write(addr, low);
-        self.mem_write(addr + 1, high);
+    fn write(&mut self, addr: u16, data: u8) {
+        self.bus.mem_write(addr, data);
+        self.end_cycle();
     }
 
-    fn read_zp_16(&self, addr: u16) -> u16 {
-        let low = self.mem_read(addr) as u16;
-        let high = self.mem_read(addr.wrapping_add(1)) as u16;
+    // the rest of the machine moves along with every CPU cycle, then the interrupt inputs are
+    // sampled. the CPU polls them during the second to last cycle of an instruction, so what
+    // decides whether an interrupt is serviced after it is what was sampled one cycle before
+    // the end
+    fn end_cycle(&mut self) {
+        self.bus.tick(1);
+
+        self.interrupt_polled = self.nmi_pending || self.irq_pending;
+
+        let nmi = self.nmi_line || self.bus.nmi_line();
+        if nmi && !self.nmi_level {
+            self.nmi_pending = true;
+        }
+        self.nmi_level = nmi;
+
+        let irq = self.irq_line || self.bus.irq_line();
+        self.irq_pending = irq && !self.status.contains(Flags::INTERRUPT_DISABLE);
+    }
+
+    fn read_zp_16(&mut self, addr: u16) -> u16 {
+        let low = self.read(addr) as u16;
+        let high = self.read(addr.wrapping_add(1)) as u16;
         (high << 8) | low
     }
 
-    pub fn run<F>(&mut self, mut callback: F)
+    // `read_zp_16` without the cycles or the side effects
+    fn peek_zp_16(&self, addr: u16) -> u16 {
+        let low = self.mem_peek(addr) as u16;
+        let high = self.mem_peek(addr.wrapping_add(1)) as u16;
+        (high << 8) | low
+    }
+
+    // returns Ok once the CPU halts on BRK, a jam is reported as an error since
+    // the loop can't make any progress after it
+    pub fn run<F>(&mut self, mut callback: F) -> Result<(), CpuError>
     where
-        F: FnMut(&mut CPU),
+        F: FnMut(&mut CPU<B>),
     {
         loop {
             callback(self);
 
-            let code = self.mem_read(self.program_counter);
-            self.program_counter += 1;
+            let result = self.step()?;
+            if result.halted {
+                return Ok(());
+            }
+            self.check_jammed(&result)?;
+        }
+    }
+
+    // runs whole instructions until at least `cycles` have elapsed, returns how many actually did.
+    // an instruction is never split, so this can overshoot by a few cycles
+    pub fn run_for_cycles(&mut self, cycles: usize) -> Result<usize, CpuError> {
+        let start = self.cycles;
+        while self.cycles - start < cycles {
+            let result = self.step()?;
+            if result.halted {
+                break;
+            }
+            self.check_jammed(&result)?;
+        }
+        Ok(self.cycles - start)
+    }
 
-            let opscode = OPS_CODES_MAP.get(&code).expect("opscode not found");
+    // steps until `condition` holds before the next instruction. returns false if the CPU
+    // halted before getting there
+    pub fn run_until<F>(&mut self, mut condition: F) -> Result<bool, CpuError>
+    where
+        F: FnMut(&CPU<B>) -> bool,
+    {
+        while !condition(self) {
+            let result = self.step()?;
+            if result.halted {
+                return Ok(false);
+            }
+            self.check_jammed(&result)?;
+        }
+        Ok(true)
+    }
 
-            // store old program counter to differentiate jumping instructions
-            let old_program_counter = self.program_counter;
+    fn check_jammed(&self, result: &StepResult) -> Result<(), CpuError> {
+        if result.jammed {
+            return Err(CpuError::Jammed {
+                pc: self.program_counter,
+                opcode: result.opcode,
+            });
+        }
+        Ok(())
+    }
+
+    // executes exactly one instruction, then services any pending interrupt.
+    // on error the program counter is left on the failing instruction
+    pub fn step(&mut self) -> Result<StepResult, CpuError> {
+        let start_cycles = self.cycles;
+        self.spend_stall();
+        let pc = self.program_counter;
+
+        if self.waiting {
+            // the clock keeps running while asleep, so the inputs still get sampled
+            self.end_cycle();
+            self.cycles += 1;
+            if !self.nmi_pending && !self.irq_asserted() {
+                return Ok(StepResult {
+                    opcode: 0xcb,
+                    cycles: self.cycles - start_cycles,
+                    halted: false,
+                    jammed: false,
+                });
+            }
 
-            match opscode.code {
-                0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
-                    self.lda(opscode.addr_mode)
-                }
-                0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => self.ldy(opscode.addr_mode),
-                0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => self.ldx(opscode.addr_mode),
-                0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => self.sta(opscode.addr_mode),
-                0x86 | 0x96 | 0x8e => self.stx(opscode.addr_mode),
-                0x84 | 0x94 | 0x8c => self.sty(opscode.addr_mode),
-                0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => {
-                    self.and(opscode.addr_mode)
-                }
-                0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => {
-                    self.eor(opscode.addr_mode)
-                }
-                0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => {
-                    self.ora(opscode.addr_mode)
-                }
-                0x24 | 0x2c => self.bit(opscode.addr_mode),
-                0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => {
-                    self.cmp(opscode.addr_mode)
-                }
-                0xc0 | 0xc4 | 0xcc => self.cpy(opscode.addr_mode),
-                0xe0 | 0xe4 | 0xec => self.cpx(opscode.addr_mode),
-                0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => {
-                    self.adc(opscode.addr_mode)
-                }
-                0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => {
-                    self.sbc(opscode.addr_mode)
-                }
-                0x90 => self.branch(!self.status.contains(Flags::CARRY)),
-                0xb0 => self.branch(self.status.contains(Flags::CARRY)),
-                0xf0 => self.branch(self.status.contains(Flags::ZERO)),
-                0xd0 => self.branch(!self.status.contains(Flags::ZERO)),
-                0x70 => self.branch(self.status.contains(Flags::OVERFLOW)),
-                0x50 => self.branch(!self.status.contains(Flags::OVERFLOW)),
-                0x10 => self.branch(!self.status.contains(Flags::NEGATIVE)),
-                0x30 => self.branch(self.status.contains(Flags::NEGATIVE)),
-                0x0a => self.asl_accumulator(),
-                0x2a => self.rol_accumulator(),
-                0x26 | 0x36 | 0x2e | 0x3e => {
-                    self.rol(opscode.addr_mode);
-                }
-                0x6a => self.ror_accumulator(),
-                0x66 | 0x76 | 0x6e | 0x7e => {
-                    self.ror(opscode.addr_mode);
-                }
-                0xc6 | 0xd6 | 0xce | 0xde => self.dec(opscode.addr_mode),
-                0xe6 | 0xf6 | 0xee | 0xfe => self.inc(opscode.addr_mode),
-                0x4a => self.lsr_accumulator(),
-                0x46 | 0x56 | 0x4e | 0x5e => {
-                    self.lsr(opscode.addr_mode);
-                }
-                0x68 => self.pla(),
-                0x08 => self.php(),
-                0x28 => self.plp(),
-                0xd8 => self.cld(),
-                0x58 => self.cli(),
-                0xb8 => self.clv(),
-                0x18 => self.clc(),
-                0x38 => self.sec(),
-                0x78 => self.sei(),
-                0xf8 => self.sed(),
-                0x48 => self.pha(),
-                0x4c => self.jmp_absolute(),
-                0x6c => self.jmp_indirect(),
-                0x20 => self.jsr(),
-                0x60 => self.rts(),
-                0xaa => self.tax(),
-                0x8a => self.txa(),
-                0xa8 => self.tay(),
-                0x98 => self.tya(),
-                0xe8 => self.inx(),
-                0xc8 => self.iny(),
-                0xca => self.dex(),
-                0x88 => self.dey(),
-                0x40 => self.rti(),
-                0xba => self.tsx(),
-                0x9a => self.txs(),
-                0xea => {}
-                0x00 => return,
-
-                // unofficial instructions
-                0x0b | 0x2b => {
-                    self.aac_anc(opscode.addr_mode);
-                }
-                0xeb => self.sbc(opscode.addr_mode),
-                0xc7 | 0xd7 | 0xCF | 0xdF | 0xdb | 0xd3 | 0xc3 => self.dcp_dcm(opscode.addr_mode),
-                0x27 | 0x37 | 0x2F | 0x3F | 0x3b | 0x33 | 0x23 => self.rla(opscode.addr_mode),
-                0x07 | 0x17 | 0x0F | 0x1f | 0x1b | 0x03 | 0x13 => self.slo_aso(opscode.addr_mode),
-                0x47 | 0x57 | 0x4F | 0x5f | 0x5b | 0x43 | 0x53 => self.sre_lse(opscode.addr_mode),
-                // SKB
-                0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 => {}
-                0xCB => self.axs_sbx_sax(opscode.addr_mode),
-                0x6b => self.arr(opscode.addr_mode),
-                0x4b => self.asr_alr(opscode.addr_mode),
-                0x04 | 0x44 | 0x64 | 0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 | 0x0c | 0x1c
-                | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc => {
-                    let addr = self.get_operand_addr(opscode.addr_mode);
-                    let _data = self.mem_read(addr);
-                    // do nothing
-                }
-                0x67 | 0x77 | 0x6f | 0x7f | 0x7b | 0x63 | 0x73 => self.rra(opscode.addr_mode),
-                0xe7 | 0xf7 | 0xef | 0xff | 0xfb | 0xe3 | 0xf3 => {
-                    self.isc_isb_ins(opscode.addr_mode)
+            // an IRQ wakes the CPU up even when it's masked, it just isn't serviced then
+            self.waiting = false;
+            self.interrupt_polled = self.nmi_pending || self.irq_pending;
+            self.poll_interrupts();
+            return Ok(StepResult {
+                opcode: 0xcb,
+                cycles: self.cycles - start_cycles,
+                halted: false,
+                jammed: false,
+            });
+        }
+
+        if self.jammed {
+            // nothing but a reset gets us out of here, not even an NMI. the opcode is only
+            // looked up for the report, so it mustn't trigger read side effects
+            let code = self.bus.mem_peek(self.program_counter);
+            return Ok(StepResult {
+                opcode: code,
+                cycles: 0,
+                halted: false,
+                jammed: true,
+            });
+        }
+
+        // halting stops short of fetching the BRK, so it doesn't take a cycle
+        if self.stop_on_brk && self.bus.mem_peek(self.program_counter) == 0x00 {
+            self.program_counter = self.program_counter.wrapping_add(1);
+            return Ok(StepResult {
+                opcode: 0x00,
+                cycles: self.cycles - start_cycles,
+                halted: true,
+                jammed: false,
+            });
+        }
+
+        let code = self.read(self.program_counter);
+
+        let opscode = opcodes::lookup(self.variant, code);
+        self.program_counter = self.program_counter.wrapping_add(1);
+
+        // store old program counter to differentiate jumping instructions
+        let old_program_counter = self.program_counter;
+
+        // single byte instructions still fetch the byte after the opcode on their second cycle,
+        // for BRK that's the padding byte
+        if opscode.len == 1 && opscode.cycles > 1 {
+            let _ = self.read(self.program_counter);
+        }
+
+        let handlers = match self.variant {
+            Variant::Wdc65C02 => &Self::CMOS_HANDLERS,
+            Variant::Nmos6502 | Variant::Ricoh2A03 => &Self::NMOS_HANDLERS,
+        };
+        handlers[code as usize](self, opscode);
+
+        if let Some(addr) = self.bus.take_fault() {
+            self.program_counter = pc;
+            return Err(CpuError::BusFault {
+                pc,
+                opcode: code,
+                addr,
+            });
+        }
+
+        self.cycles += opscode.cycles as usize;
+
+        if old_program_counter == self.program_counter {
+            self.program_counter = self.program_counter.wrapping_add((opscode.len - 1) as u16);
+        }
+
+        if !self.jammed {
+            self.spend_stall();
+            self.poll_interrupts();
+        }
+
+        Ok(StepResult {
+            opcode: code,
+            cycles: self.cycles - start_cycles,
+            halted: false,
+            jammed: self.jammed,
+        })
+    }
+}
+
+// executes one instruction. the program counter points right after the opcode
+type Handler<B> = fn(&mut CPU<B>, &'static OpCode);
+
+// gives a group of opcodes the same handler
+const fn set<B>(handlers: &mut [Handler<B>; 256], codes: &[u8], handler: Handler<B>) {
+    let mut i = 0;
+    while i < codes.len() {
+        handlers[codes[i] as usize] = handler;
+        i += 1;
+    }
+}
+
+impl<B: Mem> CPU<B> {
+    // one handler per opcode, same order as `opcodes::OPS_CODES`
+    const NMOS_HANDLERS: [Handler<B>; 256] = {
+        let mut h: [Handler<B>; 256] = [|_, _| {}; 256];
+
+        set(
+            &mut h,
+            &[0xa9, 0xa5, 0xb5, 0xad, 0xbd, 0xb9, 0xa1, 0xb1],
+            |cpu, op| cpu.lda(op.addr_mode),
+        );
+        set(&mut h, &[0xa0, 0xa4, 0xb4, 0xac, 0xbc], |cpu, op| {
+            cpu.ldy(op.addr_mode)
+        });
+        set(&mut h, &[0xa2, 0xa6, 0xb6, 0xae, 0xbe], |cpu, op| {
+            cpu.ldx(op.addr_mode)
+        });
+        set(
+            &mut h,
+            &[0x85, 0x95, 0x8d, 0x9d, 0x99, 0x81, 0x91],
+            |cpu, op| cpu.sta(op.addr_mode),
+        );
+        set(&mut h, &[0x86, 0x96, 0x8e], |cpu, op| cpu.stx(op.addr_mode));
+        set(&mut h, &[0x84, 0x94, 0x8c], |cpu, op| cpu.sty(op.addr_mode));
+        set(
+            &mut h,
+            &[0x29, 0x25, 0x35, 0x2d, 0x3d, 0x39, 0x21, 0x31],
+            |cpu, op| cpu.and(op.addr_mode),
+        );
+        set(
+            &mut h,
+            &[0x49, 0x45, 0x55, 0x4d, 0x5d, 0x59, 0x41, 0x51],
+            |cpu, op| cpu.eor(op.addr_mode),
+        );
+        set(
+            &mut h,
+            &[0x09, 0x05, 0x15, 0x0d, 0x1d, 0x19, 0x01, 0x11],
+            |cpu, op| cpu.ora(op.addr_mode),
+        );
+        set(&mut h, &[0x24, 0x2c], |cpu, op| cpu.bit(op.addr_mode));
+        set(
+            &mut h,
+            &[0xc9, 0xc5, 0xd5, 0xcd, 0xdd, 0xd9, 0xc1, 0xd1],
+            |cpu, op| cpu.cmp(op.addr_mode),
+        );
+        set(&mut h, &[0xc0, 0xc4, 0xcc], |cpu, op| cpu.cpy(op.addr_mode));
+        set(&mut h, &[0xe0, 0xe4, 0xec], |cpu, op| cpu.cpx(op.addr_mode));
+        set(
+            &mut h,
+            &[0x69, 0x65, 0x75, 0x6d, 0x7d, 0x79, 0x61, 0x71],
+            |cpu, op| cpu.adc(op.addr_mode),
+        );
+        set(
+            &mut h,
+            &[0xe9, 0xe5, 0xf5, 0xed, 0xfd, 0xf9, 0xe1, 0xf1],
+            |cpu, op| cpu.sbc(op.addr_mode),
+        );
+        h[0x90] = |cpu, _| cpu.branch(!cpu.status.contains(Flags::CARRY));
+        h[0xb0] = |cpu, _| cpu.branch(cpu.status.contains(Flags::CARRY));
+        h[0xf0] = |cpu, _| cpu.branch(cpu.status.contains(Flags::ZERO));
+        h[0xd0] = |cpu, _| cpu.branch(!cpu.status.contains(Flags::ZERO));
+        h[0x70] = |cpu, _| cpu.branch(cpu.status.contains(Flags::OVERFLOW));
+        h[0x50] = |cpu, _| cpu.branch(!cpu.status.contains(Flags::OVERFLOW));
+        h[0x10] = |cpu, _| cpu.branch(!cpu.status.contains(Flags::NEGATIVE));
+        h[0x30] = |cpu, _| cpu.branch(cpu.status.contains(Flags::NEGATIVE));
+        h[0x0a] = |cpu, _| cpu.asl_accumulator();
+        set(&mut h, &[0x06, 0x16, 0x0e, 0x1e], |cpu, op| {
+            cpu.asl(op.addr_mode);
+        });
+        h[0x2a] = |cpu, _| cpu.rol_accumulator();
+        set(&mut h, &[0x26, 0x36, 0x2e, 0x3e], |cpu, op| {
+            cpu.rol(op.addr_mode);
+        });
+        h[0x6a] = |cpu, _| cpu.ror_accumulator();
+        set(&mut h, &[0x66, 0x76, 0x6e, 0x7e], |cpu, op| {
+            cpu.ror(op.addr_mode);
+        });
+        set(&mut h, &[0xc6, 0xd6, 0xce, 0xde], |cpu, op| {
+            cpu.dec(op.addr_mode)
+        });
+        set(&mut h, &[0xe6, 0xf6, 0xee, 0xfe], |cpu, op| {
+            cpu.inc(op.addr_mode)
+        });
+        h[0x4a] = |cpu, _| cpu.lsr_accumulator();
+        set(&mut h, &[0x46, 0x56, 0x4e, 0x5e], |cpu, op| {
+            cpu.lsr(op.addr_mode);
+        });
+        h[0x68] = |cpu, _| cpu.pla();
+        h[0x08] = |cpu, _| cpu.php();
+        h[0x28] = |cpu, _| cpu.plp();
+        h[0xd8] = |cpu, _| cpu.cld();
+        h[0x58] = |cpu, _| cpu.cli();
+        h[0xb8] = |cpu, _| cpu.clv();
+        h[0x18] = |cpu, _| cpu.clc();
+        h[0x38] = |cpu, _| cpu.sec();
+        h[0x78] = |cpu, _| cpu.sei();
+        h[0xf8] = |cpu, _| cpu.sed();
+        h[0x48] = |cpu, _| cpu.pha();
+        h[0x4c] = |cpu, _| cpu.jmp_absolute();
+        h[0x6c] = |cpu, _| cpu.jmp_indirect();
+        h[0x20] = |cpu, _| cpu.jsr();
+        h[0x60] = |cpu, _| cpu.rts();
+        h[0xaa] = |cpu, _| cpu.tax();
+        h[0x8a] = |cpu, _| cpu.txa();
+        h[0xa8] = |cpu, _| cpu.tay();
+        h[0x98] = |cpu, _| cpu.tya();
+        h[0xe8] = |cpu, _| cpu.inx();
+        h[0xc8] = |cpu, _| cpu.iny();
+        h[0xca] = |cpu, _| cpu.dex();
+        h[0x88] = |cpu, _| cpu.dey();
+        h[0x40] = |cpu, _| cpu.rti();
+        h[0xba] = |cpu, _| cpu.tsx();
+        h[0x9a] = |cpu, _| cpu.txs();
+        h[0x00] = |cpu, _| cpu.brk();
+
+        // unofficial instructions
+        set(&mut h, &[0x0b, 0x2b], |cpu, op| cpu.aac_anc(op.addr_mode));
+        h[0xeb] = |cpu, op| cpu.sbc(op.addr_mode);
+        set(
+            &mut h,
+            &[0xc7, 0xd7, 0xcf, 0xdf, 0xdb, 0xd3, 0xc3],
+            |cpu, op| cpu.dcp_dcm(op.addr_mode),
+        );
+        set(
+            &mut h,
+            &[0x27, 0x37, 0x2f, 0x3f, 0x3b, 0x33, 0x23],
+            |cpu, op| cpu.rla(op.addr_mode),
+        );
+        set(
+            &mut h,
+            &[0x07, 0x17, 0x0f, 0x1f, 0x1b, 0x03, 0x13],
+            |cpu, op| cpu.slo_aso(op.addr_mode),
+        );
+        set(
+            &mut h,
+            &[0x47, 0x57, 0x4f, 0x5f, 0x5b, 0x43, 0x53],
+            |cpu, op| cpu.sre_lse(op.addr_mode),
+        );
+        // SKB
+        set(&mut h, &[0x80, 0x82, 0x89, 0xc2, 0xe2], |cpu, _| {
+            let _data = cpu.read(cpu.program_counter);
+        });
+        h[0xcb] = |cpu, op| cpu.axs_sbx_sax(op.addr_mode);
+        h[0x6b] = |cpu, op| cpu.arr(op.addr_mode);
+        h[0x4b] = |cpu, op| cpu.asr_alr(op.addr_mode);
+        set(
+            &mut h,
+            &[
+                0x04, 0x44, 0x64, 0x14, 0x34, 0x54, 0x74, 0xd4, 0xf4, 0x0c, 0x1c, 0x3c, 0x5c, 0x7c,
+                0xdc, 0xfc,
+            ],
+            |cpu, op| {
+                let (addr, page_crossed) = cpu.get_operand_addr(op.addr_mode);
+                let _data = cpu.read(addr);
+                cpu.add_page_cross_penalty(page_crossed);
+                // do nothing
+            },
+        );
+        set(
+            &mut h,
+            &[0x67, 0x77, 0x6f, 0x7f, 0x7b, 0x63, 0x73],
+            |cpu, op| cpu.rra(op.addr_mode),
+        );
+        set(
+            &mut h,
+            &[0xe7, 0xf7, 0xef, 0xff, 0xfb, 0xe3, 0xf3],
+            |cpu, op| cpu.isc_isb_ins(op.addr_mode),
+        );
+        // JAM
+        set(
+            &mut h,
+            &[
+                0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xb2, 0xd2, 0xf2,
+            ],
+            |cpu, _| cpu.jam(cpu.program_counter - 1),
+        );
+        // NOP: 0x1a 0x3a 0x5a 0x7a 0xda 0xfa 0xea, nothing to do
+        set(&mut h, &[0xa7, 0xb7, 0xaf, 0xbf, 0xa3, 0xb3], |cpu, op| {
+            cpu.lar_lax(op.addr_mode)
+        });
+        set(&mut h, &[0x87, 0x97, 0x8f, 0x83], |cpu, op| {
+            cpu.aax_sax_axs(op.addr_mode)
+        });
+        h[0xab] = |cpu, op| cpu.atx_lxa_oal(op.addr_mode);
+        h[0x8b] = |cpu, op| cpu.xaa_ane(op.addr_mode);
+        h[0xbb] = |cpu, op| cpu.lar_lae_las(op.addr_mode);
+        h[0x9b] = |cpu, op| cpu.xas_shs_tas(op.addr_mode);
+        set(&mut h, &[0x93, 0x9f], |cpu, op| cpu.axa_sha(op.addr_mode));
+        h[0x9e] = |cpu, op| cpu.sxa_shx_xas(op.addr_mode);
+        h[0x9c] = |cpu, op| cpu.sya_shy_say(op.addr_mode);
+
+        h
+    };
+
+    // same as `opcodes::OPS_CODES_65C02`: the documented NMOS handlers, with the illegal
+    // opcodes turned into NOPs and the new instructions on top
+    const CMOS_HANDLERS: [Handler<B>; 256] = {
+        let mut h = Self::NMOS_HANDLERS;
+
+        let mut code = 0;
+        while code < 256 {
+            if opcodes::NMOS_TABLE[code].name.as_bytes()[0] == b'*' {
+                h[code] = |cpu, op| cpu.nop_65c02(op.addr_mode);
+            }
+            code += 1;
+        }
+
+        h[0x80] = |cpu, _| cpu.branch(true);
+        h[0xda] = |cpu, _| cpu.stack_push(cpu.register_x);
+        h[0x5a] = |cpu, _| cpu.stack_push(cpu.register_y);
+        h[0xfa] = |cpu, _| cpu.plx();
+        h[0x7a] = |cpu, _| cpu.ply();
+        set(&mut h, &[0x64, 0x74, 0x9c, 0x9e], |cpu, op| {
+            cpu.stz(op.addr_mode)
+        });
+        set(&mut h, &[0x04, 0x0c], |cpu, op| cpu.tsb(op.addr_mode));
+        set(&mut h, &[0x14, 0x1c], |cpu, op| cpu.trb(op.addr_mode));
+        h[0x1a] = |cpu, _| cpu.inc_accumulator();
+        h[0x3a] = |cpu, _| cpu.dec_accumulator();
+        h[0x89] = |cpu, _| cpu.bit_immediate();
+        set(&mut h, &[0x34, 0x3c], |cpu, op| cpu.bit(op.addr_mode));
+        // (zp)
+        h[0x12] = |cpu, op| cpu.ora(op.addr_mode);
+        h[0x32] = |cpu, op| cpu.and(op.addr_mode);
+        h[0x52] = |cpu, op| cpu.eor(op.addr_mode);
+        h[0x72] = |cpu, op| cpu.adc(op.addr_mode);
+        h[0x92] = |cpu, op| cpu.sta(op.addr_mode);
+        h[0xb2] = |cpu, op| cpu.lda(op.addr_mode);
+        h[0xd2] = |cpu, op| cpu.cmp(op.addr_mode);
+        h[0xf2] = |cpu, op| cpu.sbc(op.addr_mode);
+        h[0x7c] = |cpu, _| cpu.jmp_indexed_indirect();
+        h[0x5c] = |cpu, _| cpu.nop_5c();
+
+        let mut bit = 0;
+        while bit < 16 {
+            h[bit << 4 | 0x07] = |cpu, op| cpu.rmb_smb(op.code);
+            h[bit << 4 | 0x0f] = |cpu, op| cpu.bbr_bbs(op.code);
+            bit += 1;
+        }
+
+        h[0xcb] = |cpu, _| cpu.wai();
+        // STP stops the clock until reset, as far as anyone can tell that's a jam
+        h[0xdb] = |cpu, _| {
+            let _ = cpu.read(cpu.program_counter);
+            cpu.jam(cpu.program_counter - 1);
+        };
+
+        h
+    };
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum InterruptKind {
+    Nmi,
+    Irq,
+    Brk,
+}
+
+#[derive(Clone, Copy)]
+struct Interrupt {
+    kind: InterruptKind,
+    vector_addr: u16,
+}
+
+const NMI: Interrupt = Interrupt {
+    kind: InterruptKind::Nmi,
+    vector_addr: 0xFFFA,
+};
+
+const IRQ: Interrupt = Interrupt {
+    kind: InterruptKind::Irq,
+    vector_addr: 0xFFFE,
+};
+
+// BRK shares the IRQ vector, the handler has to check the B flag on the stack to tell them apart
+const BRK: Interrupt = Interrupt {
+    kind: InterruptKind::Brk,
+    vector_addr: 0xFFFE,
+};
+
+fn page_crossed(a: u16, b: u16) -> bool {
+    a & 0xFF00 != b & 0xFF00
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::bus::Bus;
+    use crate::flat_ram::FlatRam;
+    use crate::rom::test::{test_rom, test_rom_asm};
+    use crate::test_bus::{Access, TestBus};
+
+    // IRQ/BRK vector -> $0300, NMI vector -> $0400, both start with a NOP. the program goes
+    // at $0200
+    fn test_cpu(variant: Variant, program: &[u8]) -> CPU<TestBus> {
+        let mut bus = TestBus::new();
+        for (i, byte) in program.iter().enumerate() {
+            bus.ram.mem_write(0x0200 + i as u16, *byte);
+        }
+        bus.ram.mem_write_u16(0xfffa, 0x0400);
+        bus.ram.mem_write_u16(0xfffe, 0x0300);
+        bus.ram.mem_write(0x0300, 0xea);
+        bus.ram.mem_write(0x0400, 0xea);
+
+        let mut cpu = CPU::new(bus);
+        cpu.variant = variant;
+        cpu.program_counter = 0x0200;
+        cpu.status = Flags::BREAK2;
+        cpu
+    }
+
+    fn asm_cpu(program: &str) -> CPU<TestBus> {
+        test_cpu(Variant::Nmos6502, &assemble(0x0200, program).unwrap())
+    }
+
+    // the accesses one instruction makes
+    fn logged_step(cpu: &mut CPU<TestBus>) -> Vec<Access> {
+        cpu.bus_mut().log.clear();
+        cpu.step().unwrap();
+        std::mem::take(&mut cpu.bus_mut().log)
+    }
+
+    fn run_program(start: u16, program: &[u8]) -> CPU<Bus> {
+        let mut bus = Bus::new(test_rom(vec![]));
+        for (i, byte) in program.iter().enumerate() {
+            bus.mem_write(start + i as u16, *byte);
+        }
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = start;
+        cpu.stop_on_brk = true;
+        cpu.run(|_| {}).unwrap();
+        cpu
+    }
+
+    // IRQ/BRK vector -> $0300, NMI vector -> $0400
+    fn bus_with_vectors(program: &[u8]) -> Bus {
+        let mut prg = vec![0; 0x8000];
+        prg[0x7FFA..].copy_from_slice(&[0x00, 0x04, 0x00, 0x00, 0x00, 0x03]);
+
+        let mut bus = Bus::new(test_rom(prg));
+        for (i, byte) in program.iter().enumerate() {
+            bus.mem_write(0x0200 + i as u16, *byte);
+        }
+        bus
+    }
+
+    #[test]
+    fn test_page_cross_and_branch_taken_penalties() {
+        let cpu = run_program(
+            0x64,
+            &[
+                0xa2, 0x01, // LDX #$01         2
+                0xbd, 0xff, 0x00, // LDA $00FF,X  4 + 1 (page cross)
+                0xf0, 0x00, // BEQ +0           2 + 1 (taken)
+                0x00,
+            ],
+        );
+        assert_eq!(cpu.cycles, 10);
+    }
+
+    #[test]
+    fn test_branch_to_another_page() {
+        let cpu = run_program(
+            0xfa,
+            &[
+                0xa2, 0x01, // LDX #$01         2
+                0xd0, 0x02, // BNE $0100        2 + 1 (taken) + 1 (page cross)
+                0xea, 0xea, 0x00,
+            ],
+        );
+        assert_eq!(cpu.cycles, 6);
+        assert_eq!(cpu.program_counter, 0x0101);
+    }
+
+    #[test]
+    fn test_brk_pushes_pc_plus_two_and_status_with_b_flag() {
+        let mut bus = bus_with_vectors(&[0x00, 0xff]); // BRK + padding byte
+        bus.mem_write(0x0300, 0x00);
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0200;
+        cpu.status = Flags::BREAK2 | Flags::CARRY;
+        // only stop once we're inside the handler
+        cpu.run(|cpu| cpu.stop_on_brk = cpu.program_counter == 0x0300)
+            .unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0301);
+        assert_eq!(cpu.stack_pointer, 0xfa);
+        assert_eq!(cpu.mem_peek_u16(0x01fc), 0x0202);
+        assert_eq!(cpu.mem_peek(0x01fb), 0b0011_0001);
+        assert!(cpu.status.contains(Flags::INTERRUPT_DISABLE));
+        assert_eq!(cpu.cycles, 7);
+    }
+
+    #[test]
+    fn test_nmi_is_edge_triggered() {
+        let mut bus = bus_with_vectors(&[0xea, 0xea, 0x00]);
+        bus.mem_write(0x0400, 0xe8); // INX
+        bus.mem_write(0x0401, 0x40); // RTI
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0200;
+        cpu.stop_on_brk = true;
+        cpu.set_nmi_line(true);
+        cpu.run(|_| {}).unwrap();
+
+        // the line is still held active, but the handler only ran once
+        assert_eq!(cpu.register_x, 1);
+        assert_eq!(cpu.program_counter, 0x0203);
+        assert_eq!(cpu.stack_pointer, 0xfd);
+    }
+
+    #[test]
+    fn test_nmi_pushes_status_without_b_flag() {
+        let mut bus = bus_with_vectors(&[0xea, 0x00]);
+        bus.mem_write(0x0400, 0x00);
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0200;
+        cpu.stop_on_brk = true;
+        cpu.set_nmi_line(true);
+        cpu.run(|_| {}).unwrap();
+
+        // the NOP finishes before the interrupt is taken
+        assert_eq!(cpu.program_counter, 0x0401);
+        assert_eq!(cpu.mem_peek_u16(0x01fc), 0x0201);
+        assert_eq!(cpu.mem_peek(0x01fb), 0b0010_0100);
+    }
+
+    #[test]
+    fn test_irq_is_masked_by_interrupt_disable() {
+        let mut bus = bus_with_vectors(&[0xea, 0x58, 0xea, 0x00]); // NOP, CLI, NOP, BRK
+        bus.mem_write(0x0300, 0x00);
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0200;
+        cpu.stop_on_brk = true;
+        cpu.set_irq_line(true);
+        cpu.run(|_| {}).unwrap();
+
+        // CLI clears the flag after the poll, so the IRQ waits for the NOP after it
+        assert_eq!(cpu.program_counter, 0x0301);
+        assert_eq!(cpu.mem_peek_u16(0x01fc), 0x0203);
+        assert!(cpu.status.contains(Flags::INTERRUPT_DISABLE));
+    }
+
+    #[test]
+    fn test_step_runs_one_instruction() {
+        let bus = bus_with_vectors(&[0xbd, 0xff, 0x01, 0x00]); // LDA $01FF,X ; BRK
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0200;
+        cpu.register_x = 1;
+        cpu.stop_on_brk = true;
+
+        let result = cpu.step().unwrap();
+        assert_eq!(result.opcode, 0xbd);
+        assert_eq!(result.cycles, 5);
+        assert!(!result.halted);
+        assert_eq!(cpu.program_counter, 0x0203);
+
+        let result = cpu.step().unwrap();
+        assert_eq!(result.opcode, 0x00);
+        assert!(result.halted);
+    }
+
+    #[test]
+    fn test_step_includes_interrupt_cycles() {
+        let mut bus = bus_with_vectors(&[0xea]);
+        bus.mem_write(0x0400, 0xea);
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0200;
+        cpu.set_nmi_line(true);
+
+        let result = cpu.step().unwrap();
+        assert_eq!(result.cycles, 2 + 7);
+        assert_eq!(cpu.program_counter, 0x0400);
+    }
+
+    #[test]
+    fn test_run_for_cycles() {
+        // JMP $0200 forever, 3 cycles each
+        let bus = bus_with_vectors(&[0x4c, 0x00, 0x02]);
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0200;
+
+        assert_eq!(cpu.run_for_cycles(9).unwrap(), 9);
+        // never splits an instruction
+        assert_eq!(cpu.run_for_cycles(10).unwrap(), 12);
+        assert_eq!(cpu.cycles, 21);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let bus = bus_with_vectors(&[0xe8, 0xe8, 0xe8, 0x00]);
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x0200;
+        cpu.stop_on_brk = true;
+
+        assert!(cpu.run_until(|cpu| cpu.program_counter == 0x0202).unwrap());
+        assert_eq!(cpu.register_x, 2);
+
+        assert!(!cpu.run_until(|cpu| cpu.program_counter == 0x0300).unwrap());
+        assert_eq!(cpu.register_x, 3);
+    }
+
+    #[test]
+    fn test_load_and_run_on_flat_ram() {
+        let mut cpu = CPU::new(FlatRam::new());
+        cpu.load(&[0xa9, 0x42, 0x85, 0x10, 0x00]); // LDA #$42 ; STA $10 ; BRK
+        cpu.reset();
+        cpu.stop_on_brk = true;
+        cpu.run(|_| {}).unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0605);
+        assert_eq!(cpu.mem_peek(0x10), 0x42);
+    }
+
+    #[test]
+    fn test_bus_fault_is_reported_with_the_failing_instruction() {
+        let mut cpu = test_cpu(Variant::Nmos6502, &[0xea, 0xad, 0x00, 0x50]); // NOP ; LDA $5000
+        cpu.bus_mut().unmapped = Some(0x5000);
+
+        let err = cpu.run(|_| {}).unwrap_err();
+        assert_eq!(
+            err,
+            CpuError::BusFault {
+                pc: 0x0201,
+                opcode: 0xad,
+                addr: 0x5000
+            }
+        );
+        assert_eq!(cpu.program_counter, 0x0201);
+    }
+
+    #[test]
+    fn test_jam_locks_up_until_reset() {
+        let mut cpu = CPU::new(FlatRam::new());
+        cpu.load(&[0xe8, 0x02, 0xe8, 0x00]); // INX ; JAM ; INX ; BRK
+        cpu.reset();
+        cpu.stop_on_brk = true;
+
+        let err = cpu.run(|_| {}).unwrap_err();
+        assert_eq!(
+            err,
+            CpuError::Jammed {
+                pc: 0x0601,
+                opcode: 0x02
+            }
+        );
+        assert!(cpu.is_jammed());
+
+        // stepping again doesn't move, and an NMI doesn't get it out either
+        cpu.set_nmi_line(true);
+        let result = cpu.step().unwrap();
+        assert!(result.jammed);
+        assert_eq!(result.opcode, 0x02);
+        assert_eq!(cpu.program_counter, 0x0601);
+        assert_eq!(cpu.register_x, 1);
+
+        cpu.reset();
+        assert!(!cpu.is_jammed());
+        assert_eq!(cpu.program_counter, 0x0600);
+    }
+
+    #[test]
+    fn test_program_counter_wraps_around_at_ffff() {
+        let mut ram = FlatRam::new();
+        ram.mem_write(0x0200, 0x4c); // JMP $FFFF
+        ram.mem_write_u16(0x0201, 0xffff);
+        ram.mem_write(0xffff, 0xa9); // LDA #$42, the operand wraps around to $0000
+        ram.mem_write(0x0000, 0x42);
+
+        let mut cpu = CPU::new(ram);
+        cpu.program_counter = 0x0200;
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0xffff);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.program_counter, 0x0001);
+
+        // a single byte instruction at the top lands right on $0000
+        cpu.bus_mut().mem_write(0xffff, 0xea); // NOP
+        cpu.program_counter = 0xffff;
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    fn run_variant(variant: Variant, program: &[u8]) -> CPU<FlatRam> {
+        let mut cpu = CPU::new(FlatRam::new());
+        cpu.variant = variant;
+        cpu.load(program);
+        cpu.reset();
+        cpu.stop_on_brk = true;
+        cpu.run(|_| {}).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_decimal_adc_on_nmos() {
+        // SED ; CLC ; LDA #$58 ; ADC #$46 ; BRK
+        let program = [0xf8, 0x18, 0xa9, 0x58, 0x69, 0x46, 0x00];
+
+        let cpu = run_variant(Variant::Nmos6502, &program);
+        assert_eq!(cpu.register_a, 0x04);
+        assert!(cpu.status.contains(Flags::CARRY));
+
+        // the 2A03 ignores the D flag
+        let cpu = run_variant(Variant::Ricoh2A03, &program);
+        assert_eq!(cpu.register_a, 0x9e);
+        assert!(!cpu.status.contains(Flags::CARRY));
+    }
+
+    #[test]
+    fn test_decimal_adc_flag_quirks() {
+        // SED ; CLC ; LDA #$99 ; ADC #$01 ; BRK
+        let cpu = run_variant(
+            Variant::Nmos6502,
+            &[0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x00],
+        );
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status.contains(Flags::CARRY));
+        // Z and N don't match the BCD result on NMOS
+        assert!(!cpu.status.contains(Flags::ZERO));
+        assert!(cpu.status.contains(Flags::NEGATIVE));
+        assert!(!cpu.status.contains(Flags::OVERFLOW));
+    }
+
+    #[test]
+    fn test_decimal_sbc_on_nmos() {
+        // SED ; SEC ; LDA #$46 ; SBC #$12 ; BRK
+        let cpu = run_variant(
+            Variant::Nmos6502,
+            &[0xf8, 0x38, 0xa9, 0x46, 0xe9, 0x12, 0x00],
+        );
+        assert_eq!(cpu.register_a, 0x34);
+        assert!(cpu.status.contains(Flags::CARRY));
+
+        // SED ; SEC ; LDA #$12 ; SBC #$21 ; BRK -> borrow
+        let cpu = run_variant(
+            Variant::Nmos6502,
+            &[0xf8, 0x38, 0xa9, 0x12, 0xe9, 0x21, 0x00],
+        );
+        assert_eq!(cpu.register_a, 0x91);
+        assert!(!cpu.status.contains(Flags::CARRY));
+    }
+
+    #[test]
+    fn test_decimal_flags_on_65c02() {
+        // SED ; CLC ; LDA #$99 ; ADC #$01 ; BRK
+        let program = [0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x00];
+
+        // NMOS takes Z from the binary sum ($9A)
+        let nmos = run_variant(Variant::Nmos6502, &program);
+        assert_eq!(nmos.register_a, 0x00);
+        assert!(!nmos.status.contains(Flags::ZERO));
+
+        // the 65C02 takes it from the result and spends an extra cycle
+        let cmos = run_variant(Variant::Wdc65C02, &program);
+        assert_eq!(cmos.register_a, 0x00);
+        assert!(cmos.status.contains(Flags::ZERO));
+        assert!(cmos.status.contains(Flags::CARRY));
+        assert_eq!(cmos.cycles, nmos.cycles + 1);
+
+        // SED ; SEC ; LDA #$12 ; SBC #$21 ; BRK
+        let cmos = run_variant(
+            Variant::Wdc65C02,
+            &[0xf8, 0x38, 0xa9, 0x12, 0xe9, 0x21, 0x00],
+        );
+        assert_eq!(cmos.register_a, 0x91);
+        assert!(cmos.status.contains(Flags::NEGATIVE));
+        assert!(!cmos.status.contains(Flags::CARRY));
+    }
+
+    #[test]
+    fn test_65c02_stack_and_store_zero() {
+        let cpu = run_variant(
+            Variant::Wdc65C02,
+            &[
+                0xa2, 0x42, // LDX #$42
+                0xda, // PHX
+                0x7a, // PLY
+                0xa9, 0xff, // LDA #$ff
+                0x85, 0x10, // STA $10
+                0x64, 0x10, // STZ $10
+                0x80, 0x02, // BRA +2
+                0xa9, 0x01, // LDA #$01 (skipped)
+                0x1a, // INC A
+                0x00,
+            ],
+        );
+
+        assert_eq!(cpu.register_y, 0x42);
+        assert_eq!(cpu.mem_peek(0x10), 0x00);
+        assert_eq!(cpu.register_a, 0x00);
+        assert!(cpu.status.contains(Flags::ZERO));
+    }
+
+    #[test]
+    fn test_65c02_test_and_set_reset_bits() {
+        let cpu = run_variant(
+            Variant::Wdc65C02,
+            &[
+                0xa9, 0x33, // LDA #$33
+                0x85, 0x20, // STA $20
+                0xa9, 0x0f, // LDA #$0f
+                0x04, 0x20, // TSB $20
+                0xa9, 0xf0, // LDA #$f0
+                0x14, 0x20, // TRB $20
+                0x00,
+            ],
+        );
+
+        assert_eq!(cpu.mem_peek(0x20), 0x0f);
+        assert!(!cpu.status.contains(Flags::ZERO));
+    }
+
+    #[test]
+    fn test_65c02_bit_branches() {
+        let cpu = run_variant(
+            Variant::Wdc65C02,
+            &[
+                0xb7, 0x10, // SMB3 $10
+                0x57, 0x11, // RMB5 $11
+                0xbf, 0x10, 0x02, // BBS3 $10,+2
+                0xa9, 0x01, // LDA #$01 (skipped)
+                0x5f, 0x11, 0x02, // BBR5 $11,+2
+                0xa9, 0x02, // LDA #$02 (skipped)
+                0x3f, 0x10, 0x02, // BBR3 $10,+2 (not taken)
+                0xa2, 0x03, // LDX #$03
+                0x00,
+            ],
+        );
+
+        assert_eq!(cpu.mem_peek(0x10), 0x08);
+        assert_eq!(cpu.register_a, 0x00);
+        assert_eq!(cpu.register_x, 0x03);
+    }
+
+    #[test]
+    fn test_65c02_zero_page_indirect() {
+        let mut cpu = CPU::new(FlatRam::new());
+        cpu.variant = Variant::Wdc65C02;
+        cpu.load(&[0xb2, 0x30, 0x00]); // LDA ($30) ; BRK
+        cpu.reset();
+        cpu.stop_on_brk = true;
+        cpu.mem_write_u16(0x30, 0x1234);
+        cpu.mem_write(0x1234, 0x77);
+        cpu.run(|_| {}).unwrap();
+
+        assert_eq!(cpu.register_a, 0x77);
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_wrap_is_fixed_on_65c02() {
+        let run = |variant| {
+            let mut cpu = CPU::new(FlatRam::new());
+            cpu.variant = variant;
+            cpu.load(&[0x6c, 0xff, 0x02]); // JMP ($02FF)
+            cpu.reset();
+            cpu.stop_on_brk = true;
+            cpu.mem_write(0x02ff, 0x00);
+            cpu.mem_write(0x0300, 0x07);
+            cpu.mem_write(0x0200, 0x08);
+            cpu.run(|_| {}).unwrap();
+            cpu.program_counter
+        };
+
+        assert_eq!(run(Variant::Nmos6502), 0x0801);
+        assert_eq!(run(Variant::Wdc65C02), 0x0701);
+    }
+
+    #[test]
+    fn test_65c02_undefined_opcodes_are_nops() {
+        let cpu = run_variant(
+            Variant::Wdc65C02,
+            &[
+                0x02, 0xa9, // 2 byte NOP
+                0x03, // 1 byte NOP
+                0x5c, 0xa9, 0xa9, // 3 byte NOP
+                0xa9, 0x05, // LDA #$05
+                0x00,
+            ],
+        );
+
+        assert_eq!(cpu.register_a, 0x05);
+        assert!(!cpu.is_jammed());
+    }
+
+    #[test]
+    fn test_wai_sleeps_until_irq() {
+        let mut bus = bus_with_vectors(&[0x58, 0xcb, 0x00]); // CLI, WAI, BRK
+        bus.mem_write(0x0300, 0x00);
+
+        let mut cpu = CPU::new(bus);
+        cpu.variant = Variant::Wdc65C02;
+        cpu.program_counter = 0x0200;
+        cpu.stop_on_brk = true;
+
+        cpu.step().unwrap(); // CLI
+        cpu.step().unwrap(); // WAI
+        let result = cpu.step().unwrap();
+        assert_eq!(result.cycles, 1);
+        assert_eq!(cpu.program_counter, 0x0202);
+
+        cpu.set_irq_line(true);
+        cpu.run(|_| {}).unwrap();
+        assert_eq!(cpu.program_counter, 0x0301);
+        assert_eq!(cpu.mem_peek_u16(0x01fc), 0x0202);
+    }
+
+    #[test]
+    fn test_stores_have_no_page_cross_penalty() {
+        let cpu = run_program(
+            0x64,
+            &[
+                0xa2, 0x01, // LDX #$01         2
+                0x9d, 0xff, 0x00, // STA $00FF,X  5
+                0x00,
+            ],
+        );
+        assert_eq!(cpu.cycles, 7);
+    }
+
+    #[test]
+    fn test_jammed_step_stays_off_the_bus() {
+        let mut cpu = test_cpu(Variant::Nmos6502, &[0x02]); // JAM
+        cpu.step().unwrap();
+        assert!(cpu.is_jammed());
+
+        assert_eq!(logged_step(&mut cpu), vec![]);
+    }
+
+    #[test]
+    fn test_read_modify_write_writes_twice() {
+        use Access::*;
+
+        let mut cpu = test_cpu(Variant::Nmos6502, &[0xfe, 0x10, 0x02]); // INC $0210,X
+        cpu.register_x = 1;
+        cpu.bus_mut().ram.mem_write(0x0211, 0x41);
+
+        assert_eq!(
+            logged_step(&mut cpu),
+            vec![
+                Read(0x0200, 0xfe),
+                Read(0x0201, 0x10),
+                Read(0x0202, 0x02),
+                Read(0x0211, 0x41), // dummy read, even without a page cross
+                Read(0x0211, 0x41),
+                Write(0x0211, 0x41), // the old value goes back first
+                Write(0x0211, 0x42),
+            ]
+        );
+
+        // the 65C02 reads again instead of writing the old value
+        let mut cpu = test_cpu(Variant::Wdc65C02, &[0xfe, 0x10, 0x02]);
+        cpu.register_x = 1;
+        cpu.bus_mut().ram.mem_write(0x0211, 0x41);
+
+        let log = logged_step(&mut cpu);
+        assert_eq!(log[5], Read(0x0211, 0x41));
+        assert_eq!(log[6], Write(0x0211, 0x42));
+        assert_eq!(log.len(), 7);
+    }
+
+    #[test]
+    fn test_indexed_read_across_page_reads_unfixed_address() {
+        use Access::*;
+
+        let mut cpu = test_cpu(Variant::Nmos6502, &[0xbd, 0xf0, 0x02]); // LDA $02F0,X
+        cpu.register_x = 0x20;
+        cpu.bus_mut().ram.mem_write(0x0310, 0x99);
+
+        assert_eq!(
+            logged_step(&mut cpu),
+            vec![
+                Read(0x0200, 0xbd),
+                Read(0x0201, 0xf0),
+                Read(0x0202, 0x02),
+                Read(0x0210, 0x00), // high byte not fixed up yet
+                Read(0x0310, 0x99),
+            ]
+        );
+
+        // no page cross, no dummy read
+        let mut cpu = test_cpu(Variant::Nmos6502, &[0xbd, 0xf0, 0x02]);
+        cpu.register_x = 0x01;
+        assert_eq!(logged_step(&mut cpu).len(), 4);
+    }
+
+    #[test]
+    fn test_jsr_fetches_target_high_byte_last() {
+        use Access::*;
+
+        let mut cpu = test_cpu(Variant::Nmos6502, &[0x20, 0x34, 0x12]); // JSR $1234
+
+        assert_eq!(
+            logged_step(&mut cpu),
+            vec![
+                Read(0x0200, 0x20),
+                Read(0x0201, 0x34),
+                Read(0x01fd, 0x00),
+                Write(0x01fd, 0x02),
+                Write(0x01fc, 0x02),
+                Read(0x0202, 0x12),
+            ]
+        );
+        assert_eq!(cpu.program_counter, 0x1234);
+    }
+
+    #[test]
+    fn test_every_cycle_is_a_bus_access() {
+        for code in 0..=0xffu8 {
+            let opscode = opcodes::lookup(Variant::Nmos6502, code);
+            if opscode.name == "*JAM" {
+                continue;
+            }
+
+            // cover page crosses, taken and not taken branches
+            for operand in [[0x80, 0x12], [0x10, 0x02]] {
+                for index in [0x00, 0xf0] {
+                    for status in [0x24, 0xe7] {
+                        let mut cpu =
+                            test_cpu(Variant::Nmos6502, &[code, operand[0], operand[1]]);
+                        for addr in 0..0x100 {
+                            cpu.bus_mut()
+                                .ram
+                                .mem_write(addr, (addr as u8).wrapping_mul(37));
+                        }
+                        cpu.register_x = index;
+                        cpu.register_y = index;
+                        cpu.status = Flags::from_bits_truncate(status);
+
+                        let start = cpu.cycles;
+                        let log = logged_step(&mut cpu);
+                        assert_eq!(
+                            log.len(),
+                            cpu.cycles - start,
+                            "{} ({:02x}) with operand {:02x?}, index {:02x}, status {:02x}",
+                            opscode.name,
+                            code,
+                            operand,
+                            index,
+                            status
+                        );
+                    }
                 }
-                // NOP
-                0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2
-                | 0xf2 | 0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => {}
-
-                0xa7 | 0xb7 | 0xaf | 0xbf | 0xa3 | 0xb3 => self.lar_lax(opscode.addr_mode),
-                0x87 | 0x97 | 0x8f | 0x83 => self.aax_sax_axs(opscode.addr_mode),
-                0xab => self.atx_lxa_oal(opscode.addr_mode),
-                0x8b => self.xaa_ane(opscode.addr_mode),
-                0xbb => self.lar_lae_las(opscode.addr_mode),
-                0x9b => self.xas_shs_tas(opscode.addr_mode),
-                0x93 => self.axa_sha(opscode.addr_mode),
-                0x9e => self.sxa_shx_xas(opscode.addr_mode),
-                0x9c => self.sya_shy_say(opscode.addr_mode),
-                _ => todo!(),
             }
+        }
+    }
+
+    fn run_asm(source: &str) -> CPU<Bus> {
+        let mut cpu = CPU::new(Bus::new(test_rom_asm(source)));
+        cpu.reset();
+        cpu.stop_on_brk = true;
+        cpu.run(|_| {}).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_0xa5_lda_zeropage_loads() {
+        let cpu = run_asm(
+            "
+                    LDX #$AB
+                    STX $10
+                    LDA $10
+                    BRK
+            ",
+        );
+        assert_eq!(cpu.register_a, 0xab);
+        assert!(!cpu.status.contains(Flags::ZERO));
+        assert!(cpu.status.contains(Flags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_0xb5_lda_zeropage_x_wraps() {
+        // (0xF8 + 0x0F) & 0xFF = 0x07
+        let cpu = run_asm(
+            "
+                    LDA #$CD
+                    STA $07
+                    LDA #0
+                    LDX #$0F
+                    LDA $F8,X
+                    BRK
+            ",
+        );
+        assert_eq!(cpu.register_a, 0xcd);
+    }
+
+    #[test]
+    fn test_lda_zeropage_x_wrap_example_edge_ff() {
+        let cpu = run_asm(
+            "
+                    LDA #$5A
+                    STA $01
+                    LDA #0
+                    LDX #$02
+                    LDA $FF,X
+                    BRK
+            ",
+        );
+        assert_eq!(cpu.register_a, 0x5a);
+    }
+
+    #[test]
+    fn test_0xad_lda_absolute_loads() {
+        let cpu = run_asm(
+            "
+                    LDA data
+                    BRK
+            data:   .byte $77
+            ",
+        );
+        assert_eq!(cpu.register_a, 0x77);
+    }
+
+    #[test]
+    fn test_0xbd_lda_absolute_x() {
+        let cpu = run_asm(
+            "
+                    LDX #$05
+                    LDA data,X
+                    BRK
+            data:   .byte 0, 0, 0, 0, 0, $44
+            ",
+        );
+        assert_eq!(cpu.register_a, 0x44);
+    }
+
+    #[test]
+    fn test_0xb9_lda_absolute_y() {
+        let cpu = run_asm(
+            "
+                    LDY #$03
+                    LDA data,Y
+                    BRK
+            data:   .byte 0, 0, 0, $99
+            ",
+        );
+        assert_eq!(cpu.register_a, 0x99);
+    }
+
+    #[test]
+    fn test_0xa1_lda_indirect_x() {
+        // X = 4, operand = $20 -> pointer at $24/$25 -> data
+        let cpu = run_asm(
+            "
+                    LDA #<data
+                    STA $24
+                    LDA #>data
+                    STA $25
+                    LDX #$04
+                    LDA ($20,X)
+                    BRK
+            data:   .byte $66
+            ",
+        );
+        assert_eq!(cpu.register_a, 0x66);
+    }
+
+    #[test]
+    fn test_0xb1_lda_indirect_y() {
+        // pointer at $20/$21 -> data, Y = 5
+        let cpu = run_asm(
+            "
+                    LDA #<data
+                    STA $20
+                    LDA #>data
+                    STA $21
+                    LDY #$05
+                    LDA ($20),Y
+                    BRK
+            data:   .byte 0, 0, 0, 0, 0, $42
+            ",
+        );
+        assert_eq!(cpu.register_a, 0x42);
+    }
+
+    #[test]
+    fn test_lda_sets_negative_flag() {
+        let cpu = run_asm("LDA #$80\nBRK");
+        assert!(cpu.status.contains(Flags::NEGATIVE));
+        assert!(!cpu.status.contains(Flags::ZERO));
+    }
+
+    fn run_unstable(unstable_ops: UnstableOps, source: &str) -> CPU<FlatRam> {
+        let mut cpu = CPU::new(FlatRam::new());
+        cpu.variant = Variant::Nmos6502;
+        cpu.unstable_ops = unstable_ops;
+        cpu.load(&assemble(0x0600, source).unwrap());
+        cpu.reset();
+        cpu.stop_on_brk = true;
+        cpu.run(|_| {}).unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_ane_and_lxa_magic_constants() {
+        let ane = "LDX #$3C\nLDA #0\n*XAA #$FF\nBRK";
+        assert_eq!(run_unstable(UnstableOps::VISUAL6502, ane).register_a, 0x2c);
+        assert_eq!(run_unstable(UnstableOps::NES, ane).register_a, 0x3c);
+
+        let lxa = "LDA #0\n*LXA #$FF\nBRK";
+        let cpu = run_unstable(UnstableOps::VISUAL6502, lxa);
+        assert_eq!((cpu.register_a, cpu.register_x), (0xee, 0xee));
+        let cpu = run_unstable(UnstableOps::NES, lxa);
+        assert_eq!((cpu.register_a, cpu.register_x), (0xff, 0xff));
+    }
+
+    #[test]
+    fn test_sh_store_crossing_a_page() {
+        // X & ($12 + 1) = $03, which also replaces the high byte of $1300
+        let source = "
+                    LDX #$0F
+                    LDY #$01
+                    *SHX $12FF,Y
+                    BRK
+        ";
+        let cpu = run_unstable(UnstableOps::VISUAL6502, source);
+        assert_eq!(cpu.mem_peek(0x0300), 0x03);
+        assert_eq!(cpu.mem_peek(0x1300), 0x00);
+
+        let cpu = run_unstable(UnstableOps::SIMPLE, source);
+        assert_eq!(cpu.mem_peek(0x0300), 0x00);
+        assert_eq!(cpu.mem_peek(0x1300), 0x03);
+    }
+
+    #[test]
+    fn test_sh_store_high_byte_wraps() {
+        // $FF + 1 wraps to 0, so everything but the address gets masked off
+        let cpu = run_unstable(
+            UnstableOps::default(),
+            "
+                    LDA #$77
+                    STA $FF00
+                    LDX #0
+                    LDY #$FF
+                    *SHY $FF00,X
+                    BRK
+            ",
+        );
+        assert_eq!(cpu.mem_peek(0xff00), 0x00);
+    }
+
+    #[test]
+    fn test_interrupt_is_polled_on_the_second_to_last_cycle() {
+        // LDA # takes 2 cycles, an NMI raised during the first one is serviced right after it
+        let mut cpu = asm_cpu("LDA #1\nNOP");
+        cpu.bus_mut().nmi_from = 1;
+        let result = cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(result.cycles, 2 + 7);
+
+        // one raised during the last cycle is too late, it waits for the NOP
+        let mut cpu = asm_cpu("LDA #1\nNOP");
+        cpu.bus_mut().nmi_from = 2;
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0202);
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0203);
+    }
+
+    #[test]
+    fn test_irq_still_taken_after_sei() {
+        // SEI sets the flag after the poll already saw the IRQ
+        let mut cpu = asm_cpu("SEI\nNOP");
+        cpu.bus_mut().irq_from = 0;
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0300);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0201);
+        // the pushed status already has I set
+        assert_eq!(cpu.bus().ram.mem_peek(0x01fb) & 0b0000_0100, 0b0000_0100);
+    }
+
+    #[test]
+    fn test_plp_delays_the_irq() {
+        // pull a status with I clear, the IRQ waits one more instruction
+        let mut cpu = asm_cpu("PLP\nNOP\nNOP");
+        cpu.bus_mut().irq_from = 0;
+        cpu.status = Flags::BREAK2 | Flags::INTERRUPT_DISABLE;
+        cpu.stack_pointer = 0xfc;
+        cpu.bus_mut().ram.mem_write(0x01fd, 0x20);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0201);
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0300);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0202);
+    }
+
+    #[test]
+    fn test_rti_restores_the_flag_before_the_poll() {
+        let mut cpu = asm_cpu("RTI");
+        cpu.bus_mut().irq_from = 0;
+        cpu.status = Flags::BREAK2 | Flags::INTERRUPT_DISABLE;
+        // status with I clear, then the return address $0250
+        cpu.stack_pointer = 0xfa;
+        for (i, byte) in [0x20, 0x50, 0x02].iter().enumerate() {
+            cpu.bus_mut().ram.mem_write(0x01fb + i as u16, *byte);
+        }
 
-            if old_program_counter == self.program_counter {
-                self.program_counter += (opscode.len - 1) as u16;
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0300);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0250);
+    }
+
+    #[test]
+    fn test_taken_branch_delays_a_late_irq() {
+        // a taken branch without a page cross takes 3 cycles but polls on its 2nd, so an IRQ
+        // showing up after that waits for the next instruction
+        let mut cpu = asm_cpu("BNE next\nnext: NOP\nNOP");
+        cpu.bus_mut().irq_from = 2;
+        assert_eq!(cpu.step().unwrap().cycles, 3);
+        assert_eq!(cpu.program_counter, 0x0202);
+
+        assert_eq!(cpu.step().unwrap().cycles, 2 + 7);
+        assert_eq!(cpu.program_counter, 0x0300);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0203);
+
+        // one that's there in time is still taken right after the branch
+        let mut cpu = asm_cpu("BNE next\nnext: NOP");
+        cpu.bus_mut().irq_from = 1;
+        assert_eq!(cpu.step().unwrap().cycles, 3 + 7);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0202);
+    }
+
+    #[test]
+    fn test_branch_across_a_page_polls_as_usual() {
+        let mut cpu = asm_cpu("BNE $01f2");
+        cpu.bus_mut().ram.mem_write(0x01f2, 0xea);
+        cpu.bus_mut().irq_from = 2;
+
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 7);
+        assert_eq!(cpu.program_counter, 0x0300);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x01f2);
+    }
+
+    #[test]
+    fn test_nmi_hijacks_brk() {
+        // BRK pushes PCH on its 3rd cycle and PCL on its 4th, an NMI by then takes the vector
+        let mut cpu = asm_cpu("BRK\nNOP");
+        cpu.bus_mut().nmi_from = 4;
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0202);
+        // it's still a BRK as far as the stack can tell
+        assert_eq!(cpu.bus().ram.mem_peek(0x01fb) & 0b0001_0000, 0b0001_0000);
+    }
+
+    #[test]
+    fn test_nmi_late_in_brk_waits_for_the_handler() {
+        // too late to hijack. the first instruction of the BRK handler runs before the NMI
+        let mut cpu = asm_cpu("BRK\nNOP");
+        cpu.bus_mut().nmi_from = 5;
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0300);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01f9), 0x0301);
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_513_or_514_cycles() {
+        // STA abs takes 4 cycles, so starting from 0 the DMA begins on an even cycle
+        let mut cpu = asm_cpu("STA $4014");
+        cpu.bus_mut().dma_port = Some(0x4014);
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 513);
+
+        let mut cpu = asm_cpu("STA $4014");
+        cpu.bus_mut().dma_port = Some(0x4014);
+        cpu.cycles = 1;
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 514);
+        assert_eq!(cpu.cycles, 1 + 4 + 514);
+    }
+
+    #[test]
+    fn test_oam_dma_on_the_nes_bus() {
+        let mut cpu = CPU::new(Bus::new(test_rom_asm(
+            "
+                    LDA #$42
+                    STA $0305
+                    LDA #$03
+                    STA $4014
+            ",
+        )));
+        cpu.reset();
+        let start = cpu.cycles;
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        let odd = (cpu.cycles + 4) % 2;
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 513 + odd);
+        assert_eq!(cpu.cycles - start, 2 + 4 + 2 + 4 + 513 + odd);
+        assert_eq!(cpu.bus().ppu().oam_data[0x05], 0x42);
+    }
+
+    #[test]
+    fn test_dmc_dma_is_cheaper_during_oam_dma() {
+        let mut cpu = asm_cpu("NOP\nNOP");
+        cpu.bus_mut().dma_port = Some(0x4014);
+        cpu.start_dma(Dma::Dmc);
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 2);
+
+        cpu.start_dma(Dma::Oam);
+        cpu.start_dma(Dma::Dmc);
+        assert_eq!(cpu.step().unwrap().cycles, 513 + 2 + 2);
+    }
+
+    #[test]
+    fn test_dmc_dma_raised_during_oam_dma_joins_the_stall() {
+        let mut cpu = asm_cpu("STA $4014\nNOP");
+        cpu.bus_mut().dma_port = Some(0x4014);
+        cpu.bus_mut().dmc_at = Some(4 + 100);
+
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 513 + 2);
+        assert_eq!(cpu.program_counter, 0x0203);
+        assert_eq!(cpu.step().unwrap().cycles, 2);
+    }
+
+    #[test]
+    fn test_nmi_during_a_stall_is_serviced_after_the_next_instruction() {
+        let mut cpu = asm_cpu("NOP\nNOP");
+        cpu.bus_mut().nmi_from = 0;
+        cpu.stall(20);
+        let result = cpu.step().unwrap();
+
+        assert_eq!(result.cycles, 20 + 2 + 7);
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0201);
+    }
+
+    #[test]
+    fn test_nmi_during_oam_dma_is_serviced_after_the_next_instruction() {
+        let mut cpu = asm_cpu("STA $4014\nNOP\nNOP");
+        cpu.bus_mut().dma_port = Some(0x4014);
+        cpu.bus_mut().nmi_from = 4 + 100;
+
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 513);
+        assert_eq!(cpu.program_counter, 0x0203);
+
+        assert_eq!(cpu.step().unwrap().cycles, 2 + 7);
+        assert_eq!(cpu.program_counter, 0x0400);
+        assert_eq!(cpu.bus().ram.mem_peek_u16(0x01fc), 0x0204);
+    }
+
+    // every cycle an instruction is counted for has to be a cycle the bus sees, or the rest
+    // of the machine falls behind. runs every opcode with and without page crosses and taken
+    // branches, and WAI on through a step asleep and the step an IRQ wakes it up
+    #[test]
+    fn test_bus_ticks_match_cycles_for_every_opcode() {
+        let mut mismatches = vec![];
+        for variant in [Variant::Nmos6502, Variant::Ricoh2A03, Variant::Wdc65C02] {
+            for code in 0..=0xffu8 {
+                for (operand, index, status, zp) in [
+                    (0x00, 0x00, Flags::empty(), 0x00),
+                    (0x80, 0x90, Flags::all(), 0xff),
+                ] {
+                    let mut cpu = test_cpu(variant, &[code, operand, operand]);
+                    for addr in 0x00..=0xff {
+                        cpu.bus_mut().ram.mem_write(addr, zp);
+                    }
+                    cpu.register_x = index;
+                    cpu.register_y = index;
+                    cpu.status = status | Flags::BREAK2;
+
+                    let mut steps = vec![cpu.step().unwrap().cycles];
+                    if cpu.waiting {
+                        steps.push(cpu.step().unwrap().cycles);
+                        cpu.set_irq_line(true);
+                        steps.push(cpu.step().unwrap().cycles);
+                    }
+                    let cycles: usize = steps.iter().sum();
+                    if cpu.bus().ticks != cycles || cpu.cycles != cycles {
+                        mismatches.push(format!(
+                            "{:?} {:02x} operand {:02x}: {} ticks, {} counted, steps {:?}",
+                            variant,
+                            code,
+                            operand,
+                            cpu.bus().ticks,
+                            cpu.cycles,
+                            steps
+                        ));
+                    }
+                }
             }
         }
+        assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
     }
-}
 
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-//
-//     #[test]
-//     fn test_0xa5_lda_zeropage_loads() {
-//         let mut cpu = CPU::new();
-//         cpu.mem_write(0x0010, 0xAB); // [$0010] = AB
-//         cpu.load_n_run(&[0xA5, 0x10, 0x00]); // LDA $10 ; BRK
-//         assert_eq!(cpu.register_a, 0xAB);
-//         assert_eq!(cpu.status & 0b0000_0010, 0); // Z clear
-//         assert_eq!(cpu.status & 0b1000_0000, 0); // N clear
-//     }
-//
-//     #[test]
-//     fn test_0xb5_lda_zeropage_x_wraps() {
-//         let mut cpu = CPU::new();
-//         // X = 0x0F, base = 0xF8 → effective = (0xF8 + 0x0F) & 0xFF = 0x07
-//         cpu.mem_write(0x0007, 0xCD);
-//         cpu.load_n_run(&[
-//             0xA2, 0x0F, // LDX #$0F
-//             0xB5, 0xF8, // LDA $F8,X
-//             0x00, // BRK
-//         ]);
-//         assert_eq!(cpu.register_a, 0xCD);
-//     }
-//
-//     #[test]
-//     fn test_0xad_lda_absolute_loads() {
-//         let mut cpu = CPU::new();
-//         cpu.mem_write(0x8000, 0x77);
-//         cpu.load_n_run(&[0xAD, 0x00, 0x80, 0x00]); // LDA $8000 ; BRK
-//         assert_eq!(cpu.register_a, 0x77);
-//     }
-//
-//     #[test]
-//     fn test_0xbd_lda_absolute_x() {
-//         let mut cpu = CPU::new();
-//         cpu.mem_write(0x8005, 0x44);
-//         cpu.load_n_run(&[
-//             0xA2, 0x05, // LDX #$05
-//             0xBD, 0x00, 0x80, // LDA $8000,X  -> $8005
-//             0x00,
-//         ]);
-//         assert_eq!(cpu.register_a, 0x44);
-//     }
-//
-//     #[test]
-//     fn test_0xb9_lda_absolute_y() {
-//         let mut cpu = CPU::new();
-//         cpu.mem_write(0x8003, 0x99);
-//         cpu.load_n_run(&[
-//             0xA0, 0x03, // LDY #$03
-//             0xB9, 0x00, 0x80, // LDA $8000,Y  -> $8003
-//             0x00,
-//         ]);
-//         assert_eq!(cpu.register_a, 0x99);
-//     }
-//
-//     #[test]
-//     fn test_0xa1_lda_indirect_x() {
-//         let mut cpu = CPU::new();
-//         // X = 4; operand = $20 → pointer in ZP at $24/$25 → $8000
-//         cpu.mem_write(0x0024, 0x00); // low
-//         cpu.mem_write(0x0025, 0x80); // high
-//         cpu.mem_write(0x8000, 0x66); // target
-//         cpu.load_n_run(&[
-//             0xA2, 0x04, // LDX #$04
-//             0xA1, 0x20, // LDA ($20,X)
-//             0x00,
-//         ]);
-//         assert_eq!(cpu.register_a, 0x66);
-//     }
-//
-//     #[test]
-//     fn test_0xb1_lda_indirect_y() {
-//         let mut cpu = CPU::new();
-//         // ZP pointer at $20/$21 -> $8000 ; Y=5 → effective $8005
-//         cpu.mem_write(0x0020, 0x00); // low
-//         cpu.mem_write(0x0021, 0x80); // high
-//         cpu.mem_write(0x8005, 0x42);
-//         cpu.load_n_run(&[
-//             0xA0, 0x05, // LDY #$05
-//             0xB1, 0x20, // LDA ($20),Y
-//             0x00,
-//         ]);
-//         assert_eq!(cpu.register_a, 0x42);
-//     }
-//
-//     #[test]
-//     fn test_lda_sets_negative_flag() {
-//         let mut cpu = CPU::new();
-//         cpu.load_n_run(&[0xA9, 0x80, 0x00]); // LDA #$80 ; BRK
-//         assert_ne!(cpu.status & 0b1000_0000, 0); // N set
-//         assert_eq!(cpu.status & 0b0000_0010, 0); // Z clear
-//     }
-//
-//     #[test]
-//     fn test_lda_zeropage_x_wrap_example_edge_ff() {
-//         let mut cpu = CPU::new();
-//         // base=$FF, X=2 → (0xFF+2)&0xFF = 0x01
-//         cpu.mem_write(0x0001, 0x5A);
-//         cpu.load_n_run(&[
-//             0xA2, 0x02, // LDX #$02
-//             0xB5, 0xFF, // LDA $FF,X  -> $01
-//             0x00,
-//         ]);
-//         assert_eq!(cpu.register_a, 0x5A);
-//     }
-// }
+    #[test]
+    fn test_halt_on_brk_stays_off_the_bus() {
+        let mut cpu = test_cpu(Variant::Nmos6502, &[0x00]); // BRK
+        cpu.stop_on_brk = true;
+
+        let result = cpu.step().unwrap();
+        assert!(result.halted);
+        assert_eq!(result.cycles, 0);
+        assert_eq!(cpu.bus().ticks, 0);
+        assert_eq!(cpu.program_counter, 0x0201);
+    }
+}
@@ -0,0 +1,28 @@
+use std::fmt;
+
+// everything that can stop the CPU in a way the frontend should know about.
+// `pc` always points at the opcode of the instruction that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    // the CPU executed a JAM/KIL opcode and is locked up until reset
+    Jammed { pc: u16, opcode: u8 },
+    // the bus couldn't service an access to `addr`
+    BusFault { pc: u16, opcode: u8, addr: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::Jammed { pc, opcode } => {
+                write!(f, "CPU jammed by opcode ${:02X} at ${:04X}", opcode, pc)
+            }
+            CpuError::BusFault { pc, opcode, addr } => write!(
+                f,
+                "bus fault accessing ${:04X} (opcode ${:02X} at ${:04X})",
+                addr, opcode, pc
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
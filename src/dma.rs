@@ -0,0 +1,11 @@
+// the DMA units that can take the bus away from the CPU. the CPU gets halted through its RDY
+// input while they run and does nothing but burn cycles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dma {
+    // a write to $4014 copying a page to the PPU's sprite memory. one cycle to halt, one more
+    // if it has to line up with a read cycle, then 256 read/write pairs: 513 or 514 cycles
+    Oam,
+    // the APU fetching the next sample byte. 4 cycles on its own, only 2 when it cuts into an
+    // OAM DMA that already has the CPU halted
+    Dmc,
+}
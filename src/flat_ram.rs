@@ -0,0 +1,31 @@
+use crate::mem::Mem;
+
+// a plain 64 KB address space with nothing mapped in it. no mirroring, no ROM, no registers.
+// good enough for easy6502 style programs (like the snake game) and CPU test suites
+pub struct FlatRam {
+    memory: Box<[u8; 0x10000]>,
+}
+
+impl FlatRam {
+    pub fn new() -> Self {
+        FlatRam {
+            memory: Box::new([0; 0x10000]),
+        }
+    }
+}
+
+impl Default for FlatRam {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mem for FlatRam {
+    fn mem_peek(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+    }
+}
@@ -2,10 +2,10 @@ use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::EventPump;
 
-use crate::cpu::CPU;
-use crate::mem::Mem;
+use rustendo::cpu::CPU;
+use rustendo::mem::Mem;
 
-pub fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
+pub fn handle_user_input<B: Mem>(cpu: &mut CPU<B>, event_pump: &mut EventPump) {
     for event in event_pump.poll_iter() {
         match event {
             Event::Quit { .. }
@@ -0,0 +1,100 @@
+use bitflags::bitflags;
+
+bitflags! {
+    // in the order the controller shifts them out
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct JoypadButton: u8 {
+        const A      = 0b0000_0001;
+        const B      = 0b0000_0010;
+        const SELECT = 0b0000_0100;
+        const START  = 0b0000_1000;
+        const UP     = 0b0001_0000;
+        const DOWN   = 0b0010_0000;
+        const LEFT   = 0b0100_0000;
+        const RIGHT  = 0b1000_0000;
+    }
+}
+
+// a standard controller behind $4016/$4017. writing 1 to bit 0 of $4016 (the strobe) keeps
+// reloading the buttons into a shift register, after writing 0 every read shifts out the next
+// button. once all 8 are out it keeps returning 1
+pub struct Joypad {
+    pub buttons: JoypadButton,
+    strobe: bool,
+    index: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            buttons: JoypadButton::empty(),
+            strobe: false,
+            index: 0,
+        }
+    }
+
+    pub fn set_button(&mut self, button: JoypadButton, pressed: bool) {
+        self.buttons.set(button, pressed);
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 == 1;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    pub fn read(&mut self) -> u8 {
+        let data = self.peek();
+        if !self.strobe && self.index < 8 {
+            self.index += 1;
+        }
+        data
+    }
+
+    // while the strobe is on it keeps reporting A
+    pub fn peek(&self) -> u8 {
+        if self.index > 7 {
+            1
+        } else {
+            (self.buttons.bits() >> self.index) & 1
+        }
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_buttons_shift_out_in_order() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(JoypadButton::A, true);
+        joypad.set_button(JoypadButton::START, true);
+        joypad.set_button(JoypadButton::RIGHT, true);
+
+        joypad.write(1);
+        joypad.write(0);
+        let bits: Vec<u8> = (0..10).map(|_| joypad.read()).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_strobe_keeps_reporting_a() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(JoypadButton::A, true);
+        joypad.write(1);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+
+        joypad.set_button(JoypadButton::A, false);
+        assert_eq!(joypad.read(), 0);
+        assert_eq!(joypad.peek(), 0);
+    }
+}
@@ -0,0 +1,21 @@
+pub mod addressing_mode;
+pub mod apu;
+pub mod assembler;
+pub mod bus;
+pub mod cpu;
+pub mod cpu_error;
+pub mod dma;
+pub mod flags;
+pub mod flat_ram;
+pub mod joypad;
+pub mod mem;
+pub mod nestest;
+pub mod opcodes;
+pub mod ppu;
+pub mod region;
+pub mod rom;
+pub mod save;
+pub mod test_bus;
+pub mod trace;
+pub mod unstable_ops;
+pub mod variant;
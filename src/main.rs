@@ -1,23 +1,45 @@
 use std::fs;
+use std::path::Path;
 
 use rand::Rng;
 use sdl2::pixels::PixelFormatEnum;
 
-use crate::{bus::Bus, cpu::CPU, mem::Mem, rom::Rom, trace::trace};
+use rustendo::{bus::Bus, cpu::CPU, mem::Mem, nestest, rom::Rom, save, trace::trace};
 
-mod addressing_mode;
-mod bus;
 mod color;
-mod cpu;
-mod flags;
 mod input;
-mod mem;
-mod opcodes;
-mod rom;
 mod screen;
-mod trace;
+
+// rustendo --nestest [nestest.nes] [nestest.log]
+// runs nestest.nes in automated mode and stops at the first line that doesn't match the log
+fn check_nestest(args: &[String]) {
+    let rom_path = args.first().map(String::as_str).unwrap_or("nestest.nes");
+    let log_path = args.get(1).map(String::as_str).unwrap_or("nestest.log");
+
+    let nes_file = fs::read(rom_path).unwrap();
+    let rom = Rom::new(&nes_file).unwrap();
+    let log = fs::read_to_string(log_path).unwrap();
+
+    let mut cpu = CPU::new(Bus::new(rom));
+    cpu.reset();
+    cpu.program_counter = nestest::AUTOMATED_START;
+
+    match nestest::check_log(&mut cpu, &log) {
+        Ok(lines) => println!("all {} lines match", lines),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "--nestest") {
+        check_nestest(&args[1..]);
+        return;
+    }
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let window = video_subsystem
@@ -59,21 +81,25 @@ fn main() {
         0x60, 0xa6, 0xff, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
     ];
 
-    let nes_file = fs::read("nestest.nes").unwrap();
+    let rom_path = Path::new("nestest.nes");
+    let nes_file = fs::read(rom_path).unwrap();
     let rom = Rom::new(&nes_file).unwrap();
 
-    let bus = Bus::new(rom);
+    let mut bus = Bus::new(rom);
+    let sav_path = save::sav_path(rom_path);
+    bus.load_save(&sav_path).unwrap();
 
     let mut cpu = CPU::new(bus);
     // cpu.load(&game_code);
     cpu.reset();
     cpu.program_counter = 0xC000;
+    cpu.stop_on_brk = true;
 
     // times 3 bcs each color has 3 components (RGB)
     let mut frame = [0 as u8; 32 * 32 * 3];
     let mut rng = rand::rng();
 
-    cpu.run(|cpu| {
+    let result = cpu.run(|cpu| {
         println!("{}", trace(cpu));
         // input::handle_user_input(cpu, &mut event_pump);
         // cpu.mem_write(0xfe, rng.random_range(1..16));
@@ -86,4 +112,14 @@ fn main() {
         //
         // std::thread::sleep(std::time::Duration::new(0, 70_000));
     });
+
+    // save even if the CPU crashed, the game may have written something worth keeping
+    if let Err(err) = cpu.bus().flush_save(&sav_path) {
+        eprintln!("couldn't write {}: {}", sav_path.display(), err);
+    }
+
+    if let Err(err) = result {
+        eprintln!("CPU crashed: {}", err);
+        std::process::exit(1);
+    }
 }
@@ -1,15 +1,65 @@
+use crate::dma::Dma;
+
 pub trait Mem {
-    fn mem_read(&self, addr: u16) -> u8;
+    // what the CPU sees when it reads. on the NES that can change things: reading PPUSTATUS
+    // clears vblank, PPUDATA moves the VRAM address, controllers shift out the next button.
+    // anything without side effects only has to implement `mem_peek`
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.mem_peek(addr)
+    }
+
+    // what `mem_read` would return right now, without touching anything. for traces,
+    // debuggers and tests, which shouldn't change what they're looking at
+    fn mem_peek(&self, addr: u16) -> u8;
+
     fn mem_write(&mut self, addr: u16, data: u8);
 
+    // returns (and clears) the address of the last access the bus couldn't service, if any.
+    // the CPU checks this after every instruction and stops with a bus fault
+    fn take_fault(&mut self) -> Option<u16> {
+        None
+    }
+
+    // returns (and clears) a DMA a device on the bus started, if any. the CPU checks this after
+    // every instruction and stalls for as long as the transfer takes
+    fn take_dma(&mut self) -> Option<Dma> {
+        None
+    }
+
+    // the interrupt inputs as driven by devices on the bus. the CPU samples them at the end of
+    // every cycle, together with whatever is set through `CPU::set_nmi_line`/`set_irq_line`
+    fn nmi_line(&self) -> bool {
+        false
+    }
+
+    fn irq_line(&self) -> bool {
+        false
+    }
+
+    // lets time pass for everything on the bus. the CPU calls this at the end of every cycle,
+    // right before it samples the interrupt lines
+    fn tick(&mut self, _cycles: usize) {}
+
+    // the (scanline, dot) the PPU is at, for buses that have one. traces show it in their
+    // PPU column, like nestest.log does
+    fn ppu_position(&self) -> Option<(u16, u16)> {
+        None
+    }
+
     // reads a 16-bit memory in little endian order
     // ex:
     //  LDA $8000 <=> A9 00 80
     //  since NES uses little endian, the CPU will read 0x00 (least significant) first then 0x80 (most significant)
     //  since people write numbers from the most significant part first, we get 0x8000
-    fn mem_read_u16(&self, addr: u16) -> u16 {
+    fn mem_read_u16(&mut self, addr: u16) -> u16 {
         let low = self.mem_read(addr) as u16;
-        let high = self.mem_read(addr + 1) as u16;
+        let high = self.mem_read(addr.wrapping_add(1)) as u16;
+        (high << 8) | low
+    }
+
+    fn mem_peek_u16(&self, addr: u16) -> u16 {
+        let low = self.mem_peek(addr) as u16;
+        let high = self.mem_peek(addr.wrapping_add(1)) as u16;
         (high << 8) | low
     }
 
@@ -0,0 +1,221 @@
+use std::fmt;
+
+use crate::{cpu::CPU, cpu_error::CpuError, mem::Mem, trace::trace};
+
+// nestest.rom starts its automated mode here, no PPU needed
+pub const AUTOMATED_START: u16 = 0xC000;
+
+// the register columns of a trace line, in the order they get compared.
+// the leading space keeps " P:" from matching inside " SP:" and " PPU:"
+const REGISTER_COLUMNS: [(&str, &str); 7] = [
+    (" A:", "A"),
+    (" X:", "X"),
+    (" Y:", "Y"),
+    (" P:", "P"),
+    (" SP:", "SP"),
+    (" PPU:", "PPU"),
+    (" CYC:", "CYC"),
+];
+
+// where a trace line first disagreed with the log
+#[derive(Debug)]
+pub struct Divergence {
+    pub line: usize, // 1 based, like an editor would show it
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug)]
+pub enum CheckError {
+    Diverged(Divergence),
+    // the CPU stopped before the log ran out
+    Crashed { line: usize, error: CpuError },
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::Diverged(divergence) => write!(
+                f,
+                "line {}: {} differs\nexpected: {}\nactual:   {}",
+                divergence.line, divergence.field, divergence.expected, divergence.actual
+            ),
+            CheckError::Crashed { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+// steps the CPU once per line of the log, comparing its trace before every instruction.
+// returns how many lines matched
+pub fn check_log<B: Mem>(cpu: &mut CPU<B>, log: &str) -> Result<usize, CheckError> {
+    let mut lines = 0;
+    for (i, expected) in log.lines().enumerate() {
+        if expected.trim().is_empty() {
+            continue;
+        }
+
+        let actual = trace(cpu);
+        if let Some(field) = first_difference(expected, &actual) {
+            return Err(CheckError::Diverged(Divergence {
+                line: i + 1,
+                field,
+                expected: expected.to_string(),
+                actual,
+            }));
+        }
+
+        cpu.step()
+            .map_err(|error| CheckError::Crashed { line: i + 1, error })?;
+        lines += 1;
+    }
+    Ok(lines)
+}
+
+// names the first column that differs between a nestest.log line and a trace line.
+// every column the log has must be in the trace too, while a log without PPU or CYC
+// columns still matches a trace that has them
+pub fn first_difference(expected: &str, actual: &str) -> Option<&'static str> {
+    let expected = Columns::parse(expected);
+    let actual = Columns::parse(actual);
+
+    if expected.address != actual.address {
+        return Some("address");
+    }
+    if expected.bytes != actual.bytes {
+        return Some("opcode bytes");
+    }
+    if expected.disassembly != actual.disassembly {
+        return Some("disassembly");
+    }
+
+    for (i, (_, name)) in REGISTER_COLUMNS.iter().enumerate() {
+        match (expected.registers[i], actual.registers[i]) {
+            (Some(expected), Some(actual)) if expected != actual => return Some(name),
+            (Some(_), None) => return Some(name),
+            (None, Some(_)) if !matches!(*name, "PPU" | "CYC") => return Some(name),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+struct Columns<'a> {
+    address: &'a str,
+    bytes: &'a str,
+    disassembly: &'a str,
+    registers: [Option<&'a str>; REGISTER_COLUMNS.len()],
+}
+
+impl<'a> Columns<'a> {
+    // "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 ... PPU:  0, 21 CYC:7"
+    // the first 48 columns are fixed width, the registers are found by their labels
+    fn parse(line: &'a str) -> Self {
+        let column =
+            |from: usize, to: usize| line.get(from..to.min(line.len())).unwrap_or("").trim();
+        let registers_from = line.find("A:").unwrap_or(line.len());
+
+        let mut registers = [None; REGISTER_COLUMNS.len()];
+        // the padding before "A:" supplies the leading space the first label needs
+        let tail = line.get(registers_from.saturating_sub(1)..).unwrap_or("");
+        let starts: Vec<Option<usize>> = REGISTER_COLUMNS
+            .iter()
+            .map(|(label, _)| tail.find(label))
+            .collect();
+
+        for (i, (label, _)) in REGISTER_COLUMNS.iter().enumerate() {
+            let Some(start) = starts[i] else { continue };
+            let value_from = start + label.len();
+            let value_to = starts
+                .iter()
+                .flatten()
+                .filter(|&&next| next > start)
+                .min()
+                .copied()
+                .unwrap_or(tail.len());
+            registers[i] = Some(tail[value_from..value_to].trim());
+        }
+
+        Columns {
+            address: column(0, 4),
+            bytes: column(6, 15),
+            disassembly: column(15, registers_from),
+            registers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::rom::test::test_rom;
+
+    const LOG_LINE: &str = "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7";
+
+    #[test]
+    fn test_matching_line_needs_every_column_of_the_log() {
+        assert_eq!(first_difference(LOG_LINE, LOG_LINE), None);
+
+        let actual =
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7";
+        assert_eq!(first_difference(LOG_LINE, actual), Some("PPU"));
+
+        let actual =
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21";
+        assert_eq!(first_difference(LOG_LINE, actual), Some("CYC"));
+
+        // an older log without the PPU column still matches a full trace
+        let expected =
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7";
+        assert_eq!(first_difference(expected, LOG_LINE), None);
+    }
+
+    #[test]
+    fn test_reports_the_first_differing_field() {
+        let actual =
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:25 SP:FC CYC:7";
+        assert_eq!(first_difference(LOG_LINE, actual), Some("P"));
+
+        let actual = "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:8";
+        assert_eq!(first_difference(LOG_LINE, actual), Some("CYC"));
+
+        let actual =
+            "C000  4C F5 C6  JMP $C6F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7";
+        assert_eq!(first_difference(LOG_LINE, actual), Some("opcode bytes"));
+
+        let expected = "C6BD  04 A9    *NOP $A9 = 00                    A:AA X:97 Y:4E P:EF SP:F5 PPU: 89,100 CYC:10177";
+        let actual =
+            "C6BD  04 A9     NOP $A9 = 00                    A:AA X:97 Y:4E P:EF SP:F5 CYC:10177";
+        assert_eq!(first_difference(expected, actual), Some("disassembly"));
+    }
+
+    #[test]
+    fn test_check_log_stops_at_the_first_divergence() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        bus.mem_write(0x0064, 0xa2); // LDX #$01
+        bus.mem_write(0x0065, 0x01);
+        bus.mem_write(0x0066, 0xca); // DEX
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x64;
+        cpu.status = crate::flags::Flags::from_bits_truncate(0x24);
+        cpu.stack_pointer = 0xfd;
+
+        let log = "\
+0064  A2 01     LDX #$01                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:0
+0066  CA        DEX                             A:00 X:01 Y:00 P:24 SP:FD PPU:  0,  6 CYC:2
+0067  00        BRK                             A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 12 CYC:4
+";
+
+        let Err(CheckError::Diverged(divergence)) = check_log(&mut cpu, log) else {
+            panic!("expected a divergence");
+        };
+        assert_eq!(divergence.line, 3);
+        assert_eq!(divergence.field, "P");
+        assert!(divergence.actual.starts_with("0067  00        BRK"));
+    }
+}
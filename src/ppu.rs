@@ -0,0 +1,548 @@
+use bitflags::bitflags;
+
+use crate::region::Region;
+use crate::rom::Mirroring;
+
+bitflags! {
+    // $2000, write only
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ControlRegister: u8 {
+        const NAMETABLE1              = 0b0000_0001;
+        const NAMETABLE2              = 0b0000_0010;
+        const VRAM_ADD_INCREMENT      = 0b0000_0100; // 0 -> +1 (across), 1 -> +32 (down)
+        const SPRITE_PATTERN_ADDR     = 0b0000_1000;
+        const BACKGROUND_PATTERN_ADDR = 0b0001_0000;
+        const SPRITE_SIZE             = 0b0010_0000;
+        const MASTER_SLAVE_SELECT     = 0b0100_0000;
+        const GENERATE_NMI            = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    // $2001, write only
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MaskRegister: u8 {
+        const GREYSCALE               = 0b0000_0001;
+        const LEFTMOST_8PXL_BACKGROUND = 0b0000_0010;
+        const LEFTMOST_8PXL_SPRITE    = 0b0000_0100;
+        const SHOW_BACKGROUND         = 0b0000_1000;
+        const SHOW_SPRITES            = 0b0001_0000;
+        const EMPHASISE_RED           = 0b0010_0000;
+        const EMPHASISE_GREEN         = 0b0100_0000;
+        const EMPHASISE_BLUE          = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    // $2002, read only. the low 5 bits aren't driven, they read back whatever was last on the
+    // PPU's data bus
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct StatusRegister: u8 {
+        const SPRITE_OVERFLOW = 0b0010_0000;
+        const SPRITE_ZERO_HIT = 0b0100_0000;
+        const VBLANK_STARTED  = 0b1000_0000;
+    }
+}
+
+pub const CHR_RAM_SIZE: usize = 8192;
+
+const DOTS_PER_SCANLINE: u16 = 341;
+const VBLANK_SCANLINE: u16 = 241;
+
+// the PPU as the CPU sees it through the 8 registers at $2000-$2007.
+// the VRAM address registers follow the names from nesdev's "PPU scrolling" page:
+//  v -> current VRAM address (15 bits)
+//  t -> temporary VRAM address, the top left onscreen tile
+//  x -> fine X scroll (3 bits)
+//  w -> first or second write toggle, shared by PPUSCROLL and PPUADDR
+pub struct NesPPU {
+    pub chr: Vec<u8>,
+    chr_is_ram: bool, // carts without CHR-ROM have 8KB of RAM there instead
+    pub palette_table: [u8; 32],
+    pub vram: [u8; 4096], // 2KB on the console, four screen carts bring the other 2KB
+    pub oam_data: [u8; 256],
+    pub mirroring: Mirroring,
+
+    pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+    pub oam_addr: u8,
+
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+    read_buffer: u8, // PPUDATA reads return the previous read's value
+    io_latch: u8,    // the last value on the PPU's data bus
+
+    pub region: Region,
+    pub frame: u64, // frames finished since power on
+    scanline: u16,  // 0-239 visible, 240 idle, 241 vblank starts, the last one is pre-render
+    dot: u16,       // 0-340
+}
+
+impl NesPPU {
+    pub fn new(chr_rom: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_is_ram = chr_rom.is_empty();
+        let chr = if chr_is_ram {
+            vec![0; CHR_RAM_SIZE]
+        } else {
+            chr_rom
+        };
+
+        NesPPU {
+            chr,
+            chr_is_ram,
+            palette_table: [0; 32],
+            vram: [0; 4096],
+            oam_data: [0; 256],
+            mirroring,
+            ctrl: ControlRegister::empty(),
+            mask: MaskRegister::empty(),
+            status: StatusRegister::empty(),
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            io_latch: 0,
+            region: Region::Ntsc,
+            frame: 0,
+            scanline: 0,
+            dot: 0,
+        }
+    }
+
+    // advances the PPU by `dots` dots. vblank starts on the second dot of scanline 241 and
+    // ends on the second dot of the pre-render line
+    pub fn tick(&mut self, dots: usize) {
+        let pre_render = self.region.scanlines_per_frame() - 1;
+        for _ in 0..dots {
+            self.dot += 1;
+            // with rendering on, NTSC skips the last dot of the pre-render line every other frame
+            if self.dot == DOTS_PER_SCANLINE - 1
+                && self.scanline == pre_render
+                && self.frame % 2 == 1
+                && self.region == Region::Ntsc
+                && self.rendering_enabled()
+            {
+                self.dot += 1;
+            }
+            if self.dot == DOTS_PER_SCANLINE {
+                self.dot = 0;
+                self.scanline += 1;
+                if self.scanline > pre_render {
+                    self.scanline = 0;
+                    self.frame += 1;
+                }
+            }
+
+            if self.dot == 1 {
+                if self.scanline == VBLANK_SCANLINE {
+                    self.status.insert(StatusRegister::VBLANK_STARTED);
+                } else if self.scanline == pre_render {
+                    self.status.remove(
+                        StatusRegister::VBLANK_STARTED
+                            | StatusRegister::SPRITE_ZERO_HIT
+                            | StatusRegister::SPRITE_OVERFLOW,
+                    );
+                }
+            }
+        }
+    }
+
+    // the /NMI output. it's a level, the CPU only reacts to it going active, so turning
+    // GENERATE_NMI on in the middle of vblank fires one right away
+    pub fn nmi_line(&self) -> bool {
+        self.status.contains(StatusRegister::VBLANK_STARTED)
+            && self.ctrl.contains(ControlRegister::GENERATE_NMI)
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.mask
+            .intersects(MaskRegister::SHOW_BACKGROUND | MaskRegister::SHOW_SPRITES)
+    }
+
+    // `addr` is already folded into $2000-$2007.
+    // most registers are write only, reading them gives back the stale value on the data bus
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        let data = match addr {
+            0x2002 => self.read_status(),
+            0x2004 => self.oam_data[self.oam_addr as usize],
+            0x2007 => self.read_data(),
+            _ => self.io_latch,
+        };
+        self.io_latch = data;
+        data
+    }
+
+    // what `read_register` would return, leaving vblank, the toggle and the VRAM address alone
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr {
+            0x2002 => self.status.bits() | (self.io_latch & 0b0001_1111),
+            0x2004 => self.oam_data[self.oam_addr as usize],
+            0x2007 => {
+                let addr = self.v & 0x3fff;
+                if addr >= 0x3f00 {
+                    self.palette_table[palette_index(addr)] | (self.io_latch & 0b1100_0000)
+                } else {
+                    self.read_buffer
+                }
+            }
+            _ => self.io_latch,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        self.io_latch = data;
+        match addr {
+            0x2000 => {
+                self.ctrl = ControlRegister::from_bits_retain(data);
+                // the nametable select bits are also bits 10-11 of t
+                self.t = (self.t & !0x0c00) | ((data as u16 & 0b11) << 10);
+            }
+            0x2001 => self.mask = MaskRegister::from_bits_retain(data),
+            0x2002 => {} // read only
+            0x2003 => self.oam_addr = data,
+            0x2004 => {
+                self.oam_data[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            0x2005 => self.write_scroll(data),
+            0x2006 => self.write_addr(data),
+            0x2007 => self.write_data(data),
+            _ => unreachable!("PPU register {:04x} isn't folded into $2000-$2007", addr),
+        }
+    }
+
+    // the PPU's side of PPUSTATUS. vblank is reported once, and the write toggle starts over
+    fn read_status(&mut self) -> u8 {
+        let data = self.status.bits() | (self.io_latch & 0b0001_1111);
+        self.status.remove(StatusRegister::VBLANK_STARTED);
+        self.w = false;
+        data
+    }
+
+    // first write: coarse X (bits 3-7) and fine X (bits 0-2)
+    // second write: coarse Y (bits 3-7) and fine Y (bits 0-2)
+    fn write_scroll(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & !0x001f) | (data as u16 >> 3);
+            self.x = data & 0b111;
+        } else {
+            self.t = (self.t & !0x73e0) | ((data as u16 & 0b111) << 12) | ((data as u16 >> 3) << 5);
+        }
+        self.w = !self.w;
+    }
+
+    // high byte first. only 14 bits fit, and v only picks up t after the second write
+    fn write_addr(&mut self, data: u8) {
+        if !self.w {
+            self.t = (self.t & 0x00ff) | ((data as u16 & 0x3f) << 8);
+        } else {
+            self.t = (self.t & 0xff00) | data as u16;
+            self.v = self.t;
+        }
+        self.w = !self.w;
+    }
+
+    fn increment_vram_addr(&mut self) {
+        let step = if self.ctrl.contains(ControlRegister::VRAM_ADD_INCREMENT) {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(step) & 0x7fff;
+    }
+
+    // reading from VRAM is slow, so PPUDATA hands out what the previous read fetched.
+    // palette reads are the exception and come back right away, the buffer gets the
+    // nametable byte "underneath" the palette instead
+    fn read_data(&mut self) -> u8 {
+        let addr = self.v & 0x3fff;
+        self.increment_vram_addr();
+
+        if addr >= 0x3f00 {
+            self.read_buffer = self.read_vram(addr - 0x1000);
+            // the top 2 bits of a palette entry come from the data bus
+            self.palette_table[palette_index(addr)] | (self.io_latch & 0b1100_0000)
+        } else {
+            let data = self.read_buffer;
+            self.read_buffer = self.read_vram(addr);
+            data
+        }
+    }
+
+    fn write_data(&mut self, data: u8) {
+        let addr = self.v & 0x3fff;
+        self.increment_vram_addr();
+
+        match addr {
+            0x0000..=0x1fff => {
+                if self.chr_is_ram {
+                    self.chr[addr as usize] = data;
+                }
+            }
+            0x2000..=0x3eff => self.vram[self.mirror_vram_addr(addr)] = data,
+            _ => self.palette_table[palette_index(addr)] = data & 0b0011_1111,
+        }
+    }
+
+    fn read_vram(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.chr[addr as usize],
+            0x2000..=0x3eff => self.vram[self.mirror_vram_addr(addr)],
+            _ => self.palette_table[palette_index(addr)],
+        }
+    }
+
+    // the 4 nametables at $2000, $2400, $2800 and $2C00 only have room for 2 in VRAM
+    //  Horizontal:
+    //    [ A ] [ a ]
+    //    [ B ] [ b ]
+    //  Vertical:
+    //    [ A ] [ B ]
+    //    [ a ] [ b ]
+    // $3000-$3EFF mirrors $2000-$2EFF
+    fn mirror_vram_addr(&self, addr: u16) -> usize {
+        let index = (addr & 0x0fff) as usize;
+        let nametable = index / 0x400;
+        let offset = index % 0x400;
+        match (&self.mirroring, nametable) {
+            (Mirroring::FOUR_SCREEN, _) => index,
+            (Mirroring::VERTICAL, 0 | 2) | (Mirroring::HORIZONTAL, 0 | 1) => offset,
+            _ => 0x400 + offset,
+        }
+    }
+
+    pub fn vram_addr(&self) -> u16 {
+        self.v
+    }
+}
+
+// $3F10/$3F14/$3F18/$3F1C are the backdrop entries of the sprite palettes, which are
+// shared with the background ones
+fn palette_index(addr: u16) -> usize {
+    let index = (addr & 0x1f) as usize;
+    match index {
+        0x10 | 0x14 | 0x18 | 0x1c => index - 0x10,
+        _ => index,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn new_empty_rom_ppu() -> NesPPU {
+        NesPPU::new(vec![0; 2048], Mirroring::HORIZONTAL)
+    }
+
+    fn set_addr(ppu: &mut NesPPU, addr: u16) {
+        ppu.write_register(0x2006, (addr >> 8) as u8);
+        ppu.write_register(0x2006, (addr & 0xff) as u8);
+    }
+
+    #[test]
+    fn test_ppu_vram_writes() {
+        let mut ppu = new_empty_rom_ppu();
+        set_addr(&mut ppu, 0x2305);
+        ppu.write_register(0x2007, 0x66);
+
+        assert_eq!(ppu.vram[0x0305], 0x66);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads_are_buffered() {
+        let mut ppu = new_empty_rom_ppu();
+        ppu.vram[0x0305] = 0x66;
+        ppu.vram[0x0306] = 0x77;
+
+        set_addr(&mut ppu, 0x2305);
+        ppu.read_register(0x2007); // load into the buffer
+        assert_eq!(ppu.read_register(0x2007), 0x66);
+        assert_eq!(ppu.read_register(0x2007), 0x77);
+        assert_eq!(ppu.vram_addr(), 0x2308);
+    }
+
+    #[test]
+    fn test_ppu_vram_reads_step_32() {
+        let mut ppu = new_empty_rom_ppu();
+        ppu.write_register(0x2000, 0b100);
+        ppu.vram[0x01ff] = 0x66;
+        ppu.vram[0x01ff + 32] = 0x77;
+        ppu.vram[0x01ff + 64] = 0x88;
+
+        set_addr(&mut ppu, 0x21ff);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x66);
+        assert_eq!(ppu.read_register(0x2007), 0x77);
+        assert_eq!(ppu.read_register(0x2007), 0x88);
+    }
+
+    // Horizontal: https://wiki.nesdev.com/w/index.php/Mirroring
+    //   [0x2000 A ] [0x2400 a ]
+    //   [0x2800 B ] [0x2C00 b ]
+    #[test]
+    fn test_vram_horizontal_mirror() {
+        let mut ppu = new_empty_rom_ppu();
+        set_addr(&mut ppu, 0x2405);
+        ppu.write_register(0x2007, 0x66); // write to a
+
+        set_addr(&mut ppu, 0x2805);
+        ppu.write_register(0x2007, 0x77); // write to B
+
+        set_addr(&mut ppu, 0x2005);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x66); // read from A
+
+        set_addr(&mut ppu, 0x2c05);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x77); // read from b
+    }
+
+    // Vertical:
+    //   [0x2000 A ] [0x2400 B ]
+    //   [0x2800 a ] [0x2C00 b ]
+    #[test]
+    fn test_vram_vertical_mirror() {
+        let mut ppu = NesPPU::new(vec![0; 2048], Mirroring::VERTICAL);
+        set_addr(&mut ppu, 0x2005);
+        ppu.write_register(0x2007, 0x66); // write to A
+
+        set_addr(&mut ppu, 0x2c05);
+        ppu.write_register(0x2007, 0x77); // write to b
+
+        set_addr(&mut ppu, 0x2805);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x66); // read from a
+
+        set_addr(&mut ppu, 0x2405);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x77); // read from B
+    }
+
+    #[test]
+    fn test_read_status_resets_latch_and_vblank() {
+        let mut ppu = new_empty_rom_ppu();
+        ppu.vram[0x0305] = 0x66;
+        ppu.status.insert(StatusRegister::VBLANK_STARTED);
+
+        ppu.write_register(0x2006, 0x21);
+        ppu.write_register(0x2006, 0x23);
+        ppu.write_register(0x2006, 0x05); // first write again after the status read below
+
+        ppu.read_register(0x2007);
+        assert_ne!(ppu.read_register(0x2007), 0x66);
+
+        assert_eq!(ppu.read_register(0x2002) >> 7, 1);
+        assert_eq!(ppu.read_register(0x2002) >> 7, 0);
+
+        ppu.write_register(0x2006, 0x23);
+        ppu.write_register(0x2006, 0x05);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x66);
+    }
+
+    #[test]
+    fn test_scroll_and_addr_share_the_toggle() {
+        let mut ppu = new_empty_rom_ppu();
+        ppu.write_register(0x2005, 0b0111_1101); // coarse X 15, fine X 5
+        ppu.write_register(0x2005, 0b0101_1110); // coarse Y 11, fine Y 6
+        assert_eq!(ppu.x, 5);
+        assert_eq!(ppu.t, (6 << 12) | (11 << 5) | 15);
+
+        // the next PPUADDR write is a first write again
+        ppu.write_register(0x2006, 0x3f);
+        assert!(ppu.w);
+    }
+
+    #[test]
+    fn test_oam_read_write() {
+        let mut ppu = new_empty_rom_ppu();
+        ppu.write_register(0x2003, 0x10);
+        ppu.write_register(0x2004, 0x66);
+        ppu.write_register(0x2004, 0x77);
+
+        ppu.write_register(0x2003, 0x10);
+        assert_eq!(ppu.read_register(0x2004), 0x66);
+
+        ppu.write_register(0x2003, 0x11);
+        assert_eq!(ppu.read_register(0x2004), 0x77);
+    }
+
+    #[test]
+    fn test_palette_mirrors_and_unbuffered_reads() {
+        let mut ppu = new_empty_rom_ppu();
+        set_addr(&mut ppu, 0x3f10);
+        ppu.write_register(0x2007, 0x21);
+
+        // no dummy read needed for palettes
+        set_addr(&mut ppu, 0x3f00);
+        assert_eq!(ppu.read_register(0x2007), 0x21);
+        // and $3F20+ mirrors the 32 entries
+        set_addr(&mut ppu, 0x3f30);
+        assert_eq!(ppu.read_register(0x2007) & 0x3f, 0x21);
+    }
+
+    #[test]
+    fn test_chr_ram_is_writable() {
+        let mut ppu = NesPPU::new(vec![], Mirroring::HORIZONTAL);
+        set_addr(&mut ppu, 0x0010);
+        ppu.write_register(0x2007, 0x55);
+        assert_eq!(ppu.chr[0x10], 0x55);
+
+        let mut ppu = new_empty_rom_ppu();
+        set_addr(&mut ppu, 0x0010);
+        ppu.write_register(0x2007, 0x55);
+        assert_eq!(ppu.chr[0x10], 0x00);
+    }
+
+    #[test]
+    fn test_vblank_timing() {
+        let mut ppu = new_empty_rom_ppu();
+        ppu.tick(241 * 341);
+        assert_eq!((ppu.scanline(), ppu.dot()), (241, 0));
+        assert!(!ppu.status.contains(StatusRegister::VBLANK_STARTED));
+
+        ppu.tick(1);
+        assert!(ppu.status.contains(StatusRegister::VBLANK_STARTED));
+        assert!(!ppu.nmi_line());
+        ppu.write_register(0x2000, 0b1000_0000);
+        assert!(ppu.nmi_line());
+
+        ppu.tick(20 * 341);
+        assert_eq!((ppu.scanline(), ppu.dot()), (261, 1));
+        assert!(!ppu.status.contains(StatusRegister::VBLANK_STARTED));
+        assert!(!ppu.nmi_line());
+
+        ppu.tick(340);
+        assert_eq!((ppu.scanline(), ppu.dot(), ppu.frame), (0, 0, 1));
+    }
+
+    #[test]
+    fn test_odd_frames_are_one_dot_shorter_when_rendering() {
+        let mut ppu = new_empty_rom_ppu();
+        ppu.mask = MaskRegister::SHOW_BACKGROUND;
+        ppu.tick(262 * 341);
+        assert_eq!(ppu.frame, 1);
+        ppu.tick(262 * 341 - 1);
+        assert_eq!((ppu.frame, ppu.scanline(), ppu.dot()), (2, 0, 0));
+
+        // PAL frames are longer and always the same length
+        let mut ppu = new_empty_rom_ppu();
+        ppu.region = Region::Pal;
+        ppu.mask = MaskRegister::SHOW_BACKGROUND;
+        ppu.tick(2 * 312 * 341);
+        assert_eq!((ppu.frame, ppu.scanline(), ppu.dot()), (2, 0, 0));
+    }
+}
@@ -0,0 +1,27 @@
+// the CPU, PPU and APU are all divided down from one master clock, by amounts that depend
+// on the console's TV system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    // 3 PPU dots per CPU cycle, 262 scanlines per frame
+    #[default]
+    Ntsc,
+    // 3.2 PPU dots per CPU cycle, 312 scanlines per frame
+    Pal,
+}
+
+impl Region {
+    // PPU dots per CPU cycle in fifths of a dot, so PAL's 3.2 stays a whole number
+    pub fn ppu_fifths_per_cpu_cycle(&self) -> usize {
+        match self {
+            Region::Ntsc => 15,
+            Region::Pal => 16,
+        }
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal => 312,
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use crate::region::Region;
+
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
 const PRG_ROM_PAGE_SIZE: usize = 16384;
 const CHR_ROM_PAGE_SIZE: usize = 8192;
+const PRG_RAM_PAGE_SIZE: usize = 8192;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mirroring {
     VERTICAL,
     HORIZONTAL,
@@ -10,7 +13,7 @@ pub enum Mirroring {
 }
 
 // so the ROM dump contains 4 things
-//  1. header -> mapper and screen Mirroring
+//  1. header -> mapper, screen Mirroring and the PRG RAM
 //  2. PRG ROM
 //  3. CHR ROM
 pub struct Rom {
@@ -18,6 +21,9 @@ pub struct Rom {
     pub chr_rom: Vec<u8>,
     pub mapper: u8,
     pub screen_mirroring: Mirroring,
+    pub prg_ram_size: usize,
+    pub battery: bool, // the PRG RAM keeps its contents when the console is off
+    pub region: Region,
 }
 
 impl Rom {
@@ -45,9 +51,19 @@ impl Rom {
         };
 
         let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize & CHR_ROM_PAGE_SIZE;
+        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
 
         let trainer = raw[6] & 0b100 != 0;
+        let battery = raw[6] & 0b10 != 0; // bit 1
+
+        // in 8KB units, 0 still means 8KB since older dumps leave it empty
+        let prg_ram_size = raw[8].max(1) as usize * PRG_RAM_PAGE_SIZE;
+        // hardly any dump sets this, so it's only a hint
+        let region = if raw[9] & 1 != 0 {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
 
         let prg_rom_start = 16 + if trainer { 512 } else { 0 };
         let chr_rom_start = prg_rom_start + prg_rom_size;
@@ -57,12 +73,16 @@ impl Rom {
             prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
             chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
             screen_mirroring,
+            prg_ram_size,
+            battery,
+            region,
         })
     }
 }
 
 pub mod test {
     use super::*;
+    use crate::assembler::assemble;
 
     struct TestRom {
         header: Vec<u8>,
@@ -105,6 +125,17 @@ pub mod test {
         Rom::new(&test_rom).unwrap()
     }
 
+    // assembles `source` into PRG-ROM at $8000 and points the reset vector there, unless the
+    // program sets it itself with `.org $fffc`
+    pub fn test_rom_asm(source: &str) -> Rom {
+        let mut program = assemble(0x8000, source).unwrap_or_else(|err| panic!("{}", err));
+        program.resize(2 * PRG_ROM_PAGE_SIZE, 0);
+        if program[0x7ffc..0x7ffe] == [0, 0] {
+            program[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+        }
+        test_rom(program)
+    }
+
     #[test]
     fn test() {
         let test_rom = create_rom(TestRom {
@@ -122,6 +153,24 @@ pub mod test {
         assert_eq!(rom.prg_rom, vec!(1; 2 * PRG_ROM_PAGE_SIZE));
         assert_eq!(rom.mapper, 3);
         assert_eq!(rom.screen_mirroring, Mirroring::VERTICAL);
+        assert_eq!(rom.prg_ram_size, PRG_RAM_PAGE_SIZE);
+        assert!(!rom.battery);
+    }
+
+    #[test]
+    fn test_battery_and_prg_ram_size() {
+        let test_rom = create_rom(TestRom {
+            header: vec![
+                0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01, 0b10, 00, 0x04, 00, 00, 00, 00, 00, 00, 00,
+            ],
+            trainer: None,
+            pgp_rom: vec![1; PRG_ROM_PAGE_SIZE],
+            chr_rom: vec![2; CHR_ROM_PAGE_SIZE],
+        });
+
+        let rom: Rom = Rom::new(&test_rom).unwrap();
+        assert!(rom.battery);
+        assert_eq!(rom.prg_ram_size, 4 * PRG_RAM_PAGE_SIZE);
     }
 
     #[test]
@@ -0,0 +1,78 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// battery backed PRG RAM lives next to the ROM, "zelda.nes" -> "zelda.sav"
+pub fn sav_path(rom_path: &Path) -> PathBuf {
+    rom_path.with_extension("sav")
+}
+
+// copies the save into `ram`. a missing file just means nothing was saved yet, so `ram`
+// is left alone. returns whether there was a save
+pub fn load(path: &Path, ram: &mut [u8]) -> io::Result<bool> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+
+    let len = data.len().min(ram.len());
+    ram[..len].copy_from_slice(&data[..len]);
+    Ok(true)
+}
+
+// writes a temporary file next to the save and renames it over the old one. the rename
+// replaces it in one go, so a crash halfway through leaves the old save, never half of each
+pub fn store(path: &Path, ram: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("sav.tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(ram)?;
+    // make sure the data is on disk before the rename makes it the save
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rustendo-{}-{}.sav", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_sav_path_is_next_to_the_rom() {
+        assert_eq!(
+            sav_path(Path::new("roms/zelda.nes")),
+            PathBuf::from("roms/zelda.sav")
+        );
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let path = temp_path("roundtrip");
+        store(&path, &[1, 2, 3, 4]).unwrap();
+        assert!(!path.with_extension("sav.tmp").exists());
+
+        let mut ram = [0xff; 6];
+        assert!(load(&path, &mut ram).unwrap());
+        assert_eq!(ram, [1, 2, 3, 4, 0xff, 0xff]);
+
+        // overwriting replaces the old save
+        store(&path, &[5; 6]).unwrap();
+        assert!(load(&path, &mut ram).unwrap());
+        assert_eq!(ram, [5; 6]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_save_leaves_ram_alone() {
+        let path = temp_path("missing");
+        let mut ram = [7; 4];
+        assert!(!load(&path, &mut ram).unwrap());
+        assert_eq!(ram, [7; 4]);
+    }
+}
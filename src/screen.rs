@@ -1,11 +1,12 @@
-use crate::{color, cpu::CPU, mem::Mem};
+use crate::color;
+use rustendo::{cpu::CPU, mem::Mem};
 
-pub fn should_update_screen(cpu: &CPU, frame: &mut [u8]) -> bool {
+pub fn should_update_screen<B: Mem>(cpu: &CPU<B>, frame: &mut [u8]) -> bool {
     let mut should_update = false;
     let mut frame_idx = 0;
 
     for addr in 0x0200..0x0600 {
-        let color_byte = cpu.mem_read(addr as u16);
+        let color_byte = cpu.mem_peek(addr as u16);
         let (r, g, b) = color::get_rgb_color(color_byte);
 
         // only update the screen if the index changes aka the color
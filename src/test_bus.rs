@@ -0,0 +1,99 @@
+use crate::{dma::Dma, flat_ram::FlatRam, mem::Mem};
+
+// one bus access, in the order the CPU made them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+// flat RAM for CPU tests that keeps track of everything the CPU does to it, and can be scripted
+// to act like the devices on a real bus. left at its defaults it's just RAM with a log
+pub struct TestBus {
+    pub ram: FlatRam,
+    pub log: Vec<Access>,
+    pub ticks: usize, // cycles that passed so far
+    // the NMI and IRQ lines go active from this cycle on, usize::MAX keeps them inactive
+    pub nmi_from: usize,
+    pub irq_from: usize,
+    // writing here starts an OAM DMA, like $4014 on the NES
+    pub dma_port: Option<u16>,
+    // a DMC fetch is requested on this cycle, like the APU would
+    pub dmc_at: Option<usize>,
+    // reading from here faults, as if nothing was mapped there
+    pub unmapped: Option<u16>,
+    dma: Vec<Dma>,
+    fault: Option<u16>,
+}
+
+impl TestBus {
+    pub fn new() -> Self {
+        TestBus {
+            ram: FlatRam::new(),
+            log: vec![],
+            ticks: 0,
+            nmi_from: usize::MAX,
+            irq_from: usize::MAX,
+            dma_port: None,
+            dmc_at: None,
+            unmapped: None,
+            dma: vec![],
+            fault: None,
+        }
+    }
+}
+
+impl Default for TestBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mem for TestBus {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        if self.unmapped == Some(addr) {
+            self.fault = Some(addr);
+        }
+        let data = self.ram.mem_read(addr);
+        self.log.push(Access::Read(addr, data));
+        data
+    }
+
+    // peeking isn't something the CPU does on the bus, so it doesn't get logged
+    fn mem_peek(&self, addr: u16) -> u8 {
+        self.ram.mem_peek(addr)
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        if self.dma_port == Some(addr) {
+            self.dma.push(Dma::Oam);
+        }
+        self.log.push(Access::Write(addr, data));
+        self.ram.mem_write(addr, data);
+    }
+
+    fn take_fault(&mut self) -> Option<u16> {
+        self.fault.take()
+    }
+
+    fn take_dma(&mut self) -> Option<Dma> {
+        (!self.dma.is_empty()).then(|| self.dma.remove(0))
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.ticks >= self.nmi_from
+    }
+
+    fn irq_line(&self) -> bool {
+        self.ticks >= self.irq_from
+    }
+
+    fn tick(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.ticks += 1;
+            if self.dmc_at == Some(self.ticks) {
+                self.dma.push(Dma::Dmc);
+            }
+        }
+    }
+}
@@ -1,32 +1,39 @@
 use crate::addressing_mode::AddressingMode;
 use crate::cpu::CPU;
 use crate::mem::Mem;
-use crate::opcodes::{self, OPS_CODES_MAP};
+use crate::opcodes;
+use crate::variant::Variant;
 
-pub fn trace(cpu: &CPU) -> String {
-    let code = cpu.mem_read(cpu.program_counter);
-    let ops = OPS_CODES_MAP.get(&code).unwrap();
+pub fn trace<B: Mem>(cpu: &CPU<B>) -> String {
+    let code = cpu.mem_peek(cpu.program_counter);
+    let ops = opcodes::lookup(cpu.variant, code);
 
+    let cmos = cpu.variant == Variant::Wdc65C02;
     let begin = cpu.program_counter;
     let mut hex_dump = vec![];
     hex_dump.push(code);
 
     let (mem_addr, stored_value) = match ops.addr_mode {
         AddressingMode::Immediate | AddressingMode::Implied => (0, 0),
+        AddressingMode::ZeroPage_Relative => {
+            let addr = cpu.mem_peek(begin.wrapping_add(1)) as u16;
+            (addr, cpu.mem_peek(addr))
+        }
         _ => {
-            let addr = cpu.get_effective_addr(ops.addr_mode, begin + 1);
-            (addr, cpu.mem_read(addr))
+            let (addr, _) = cpu.get_effective_addr(ops.addr_mode, begin.wrapping_add(1));
+            (addr, cpu.mem_peek(addr))
         }
     };
 
     let tmp = match ops.len {
         1 => match ops.code {
             0x0a | 0x4a | 0x2a | 0x6a => format!("A "),
+            0x1a | 0x3a if cmos => "A ".to_string(),
             _ => String::from(""),
         },
         2 => {
-            let address: u8 = cpu.mem_read(begin + 1);
-            // let value = cpu.mem_read(address));
+            let address: u8 = cpu.mem_peek(begin.wrapping_add(1));
+            // let value = cpu.mem_peek(address));
             hex_dump.push(address);
 
             match ops.addr_mode {
@@ -54,6 +61,10 @@ pub fn trace(cpu: &CPU) -> String {
                     mem_addr,
                     stored_value
                 ),
+                AddressingMode::Indirect_ZeroPage => format!(
+                    "(${:02x}) = {:04x} = {:02x}",
+                    address, mem_addr, stored_value
+                ),
                 AddressingMode::Implied => {
                     // assuming local jumps: BNE, BVS, etc....
                     let address: usize =
@@ -68,27 +79,31 @@ pub fn trace(cpu: &CPU) -> String {
             }
         }
         3 => {
-            let address_lo = cpu.mem_read(begin + 1);
-            let address_hi = cpu.mem_read(begin + 2);
+            let address_lo = cpu.mem_peek(begin.wrapping_add(1));
+            let address_hi = cpu.mem_peek(begin.wrapping_add(2));
             hex_dump.push(address_lo);
             hex_dump.push(address_hi);
 
-            let address = cpu.mem_read_u16(begin + 1);
+            let address = cpu.mem_peek_u16(begin.wrapping_add(1));
 
             match ops.addr_mode {
                 AddressingMode::Implied => {
                     if ops.code == 0x6c {
                         //jmp indirect
-                        let jmp_addr = if address & 0x00FF == 0x00FF {
-                            let lo = cpu.mem_read(address);
-                            let hi = cpu.mem_read(address & 0xFF00);
+                        let jmp_addr = if address & 0x00FF == 0x00FF && !cmos {
+                            let lo = cpu.mem_peek(address);
+                            let hi = cpu.mem_peek(address & 0xFF00);
                             (hi as u16) << 8 | (lo as u16)
                         } else {
-                            cpu.mem_read_u16(address)
+                            cpu.mem_peek_u16(address)
                         };
 
-                        // let jmp_addr = cpu.mem_read_u16(address);
+                        // let jmp_addr = cpu.mem_peek_u16(address);
                         format!("(${:04x}) = {:04x}", address, jmp_addr)
+                    } else if ops.code == 0x7c && cmos {
+                        let jmp_addr =
+                            cpu.mem_peek_u16(address.wrapping_add(cpu.register_x as u16));
+                        format!("(${:04x},X) = {:04x}", address, jmp_addr)
                     } else {
                         format!("${:04x}", address)
                     }
@@ -102,6 +117,10 @@ pub fn trace(cpu: &CPU) -> String {
                     "${:04x},Y @ {:04x} = {:02x}",
                     address, mem_addr, stored_value
                 ),
+                AddressingMode::ZeroPage_Relative => {
+                    let target = (begin as usize + 3).wrapping_add((address_hi as i8) as usize);
+                    format!("${:02x} = {:02x}, ${:04x}", mem_addr, stored_value, target)
+                }
                 _ => panic!(
                     "unexpected addressing mode {:?} has ops-len 3. code {:02x}",
                     ops.addr_mode, ops.code
@@ -120,9 +139,23 @@ pub fn trace(cpu: &CPU) -> String {
         .trim()
         .to_string();
 
+    let jam_marker = if cpu.is_jammed() { " JAMMED" } else { "" };
+    let ppu_str = match cpu.bus().ppu_position() {
+        Some((scanline, dot)) => format!(" PPU:{:3},{:3}", scanline, dot),
+        None => String::from(""),
+    };
+
     format!(
-        "{:47} A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x}",
-        asm_str, cpu.register_a, cpu.register_x, cpu.register_y, cpu.status, cpu.stack_pointer,
+        "{:47} A:{:02x} X:{:02x} Y:{:02x} P:{:02x} SP:{:02x}{} CYC:{}{}",
+        asm_str,
+        cpu.register_a,
+        cpu.register_x,
+        cpu.register_y,
+        cpu.status,
+        cpu.stack_pointer,
+        ppu_str,
+        cpu.cycles,
+        jam_marker,
     )
     .to_ascii_uppercase()
 }
@@ -144,23 +177,25 @@ mod test {
 
         let mut cpu = CPU::new(bus);
         cpu.program_counter = 0x64;
+        cpu.stop_on_brk = true;
         cpu.register_a = 1;
         cpu.register_x = 2;
         cpu.register_y = 3;
         let mut result: Vec<String> = vec![];
         cpu.run(|cpu| {
             result.push(trace(cpu));
-        });
+        })
+        .unwrap();
         assert_eq!(
-            "0064  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD",
+            "0064  A2 01     LDX #$01                        A:01 X:02 Y:03 P:24 SP:FD PPU:  0,  0 CYC:0",
             result[0]
         );
         assert_eq!(
-            "0066  CA        DEX                             A:01 X:01 Y:03 P:24 SP:FD",
+            "0066  CA        DEX                             A:01 X:01 Y:03 P:24 SP:FD PPU:  0,  6 CYC:2",
             result[1]
         );
         assert_eq!(
-            "0067  88        DEY                             A:01 X:00 Y:03 P:26 SP:FD",
+            "0067  88        DEY                             A:01 X:00 Y:03 P:26 SP:FD PPU:  0, 12 CYC:4",
             result[2]
         );
     }
@@ -181,14 +216,42 @@ mod test {
 
         let mut cpu = CPU::new(bus);
         cpu.program_counter = 0x64;
+        cpu.stop_on_brk = true;
         cpu.register_y = 0;
         let mut result: Vec<String> = vec![];
         cpu.run(|cpu| {
             result.push(trace(cpu));
-        });
+        })
+        .unwrap();
         assert_eq!(
-            "0064  11 33     ORA ($33),Y = 0400 @ 0400 = AA  A:00 X:00 Y:00 P:24 SP:FD",
+            "0064  11 33     ORA ($33),Y = 0400 @ 0400 = AA  A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:0",
             result[0]
         );
     }
+
+    #[test]
+    fn test_trace_leaves_ppu_registers_alone() {
+        let mut bus = Bus::new(test_rom(vec![]));
+        // LDA $2002
+        bus.mem_write(100, 0xad);
+        bus.mem_write(101, 0x02);
+        bus.mem_write(102, 0x20);
+        bus.ppu_mut()
+            .status
+            .insert(crate::ppu::StatusRegister::VBLANK_STARTED);
+
+        let mut cpu = CPU::new(bus);
+        cpu.program_counter = 0x64;
+        assert_eq!(
+            "0064  AD 02 20  LDA $2002 = 80                  A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:0",
+            trace(&cpu)
+        );
+        assert_eq!(
+            "0064  AD 02 20  LDA $2002 = 80                  A:00 X:00 Y:00 P:24 SP:FD PPU:  0,  0 CYC:0",
+            trace(&cpu)
+        );
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.register_a, 0x80);
+    }
 }
@@ -0,0 +1,46 @@
+// the unstable illegal opcodes don't behave the same on every chip, they depend on analog
+// effects that vary between production runs, temperature, and what else is on the bus.
+// this picks one behavior, the presets cover what emulators and test ROMs usually settle on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnstableOps {
+    // ANE/XAA #imm: A = (A | ane_magic) & X & imm
+    pub ane_magic: u8,
+    // LXA/ATX #imm: A = X = (A | lxa_magic) & imm
+    pub lxa_magic: u8,
+    // SHA, SHX, SHY and TAS store `value & (H + 1)`, H being the high byte of the address
+    // before indexing. when indexing crosses a page the stored value also ends up as the
+    // high byte of the address it's written to
+    pub sh_page_cross_uses_value_as_high_byte: bool,
+}
+
+impl UnstableOps {
+    // what visual6502's transistor level simulation does, and what the single step test
+    // vectors were generated with
+    pub const VISUAL6502: UnstableOps = UnstableOps {
+        ane_magic: 0xee,
+        lxa_magic: 0xee,
+        sh_page_cross_uses_value_as_high_byte: true,
+    };
+
+    // most NES emulators treat ANE and LXA as if the magic constant was $FF, which
+    // is also what blargg's instr_test-v5 expects from LXA
+    pub const NES: UnstableOps = UnstableOps {
+        ane_magic: 0xff,
+        lxa_magic: 0xff,
+        sh_page_cross_uses_value_as_high_byte: true,
+    };
+
+    // the simplest take, ignoring the page cross glitch, for emulators that just
+    // write to the indexed address
+    pub const SIMPLE: UnstableOps = UnstableOps {
+        ane_magic: 0xff,
+        lxa_magic: 0xff,
+        sh_page_cross_uses_value_as_high_byte: false,
+    };
+}
+
+impl Default for UnstableOps {
+    fn default() -> Self {
+        UnstableOps::NES
+    }
+}
@@ -0,0 +1,21 @@
+// which flavour of 6502 the CPU core behaves like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    // the original MOS 6502, with a working decimal mode
+    Nmos6502,
+    // the NES CPU. a 6502 with the decimal mode circuitry cut off, so the D flag
+    // can still be set and cleared but ADC/SBC always work in binary
+    Ricoh2A03,
+    // the CMOS rewrite. new instructions, no illegal opcodes, JMP ($xxFF) fixed
+    // and decimal mode flags that actually match the result
+    Wdc65C02,
+}
+
+impl Variant {
+    pub fn has_decimal_mode(&self) -> bool {
+        match self {
+            Variant::Nmos6502 | Variant::Wdc65C02 => true,
+            Variant::Ricoh2A03 => false,
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// runs Klaus Dormann's 6502 test suite, https://github.com/Klaus2m5/6502_65C02_functional_tests
+// the binaries aren't in the repo, point these at local copies:
+//
+//   KLAUS_FUNCTIONAL_TEST=path/to/6502_functional_test.bin
+//   KLAUS_DECIMAL_TEST=path/to/6502_decimal_test.bin
+//
+// and run them with `cargo test --test klaus_dormann -- --ignored`.
+//
+// both tests end in a trap, a branch or jump to itself. for the functional test the address of
+// the trap tells whether it passed, KLAUS_SUCCESS_ADDR overrides the one of the stock build.
+// the decimal test reports through its ERROR byte instead
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+
+use rustendo::{cpu::CPU, flat_ram::FlatRam, mem::Mem, trace::trace, variant::Variant};
+
+// where the stock builds of the tests start and succeed
+const FUNCTIONAL_ENTRY: u16 = 0x0400;
+const FUNCTIONAL_SUCCESS: u16 = 0x3469;
+const DECIMAL_LOAD: u16 = 0x0200;
+const DECIMAL_ERROR: u16 = 0x000b;
+
+// the functional test takes about 30 million instructions
+const MAX_INSTRUCTIONS: usize = 200_000_000;
+const TRACE_LINES: usize = 300;
+
+fn load(image: &[u8], at: u16, entry: u16) -> CPU<FlatRam> {
+    let mut ram = FlatRam::new();
+    for (i, byte) in image.iter().enumerate() {
+        ram.mem_write(at.wrapping_add(i as u16), *byte);
+    }
+
+    let mut cpu = CPU::new(ram);
+    cpu.variant = Variant::Nmos6502;
+    cpu.program_counter = entry;
+    cpu
+}
+
+// steps until the program counter stops moving. returns the trap address and how many
+// instructions ran before it, or None if it never got there
+fn run_until_trap<B: Mem>(cpu: &mut CPU<B>, max_instructions: usize) -> Option<(u16, usize)> {
+    for instructions in 0..max_instructions {
+        let pc = cpu.program_counter;
+        let result = cpu
+            .step()
+            .unwrap_or_else(|err| panic!("CPU crashed: {}", err));
+        if result.jammed || cpu.program_counter == pc {
+            return Some((pc, instructions));
+        }
+    }
+    None
+}
+
+// the runs are deterministic, so rather than formatting a trace line for all of the
+// millions of instructions we replay from the start and only keep the tail
+fn trace_tail(make_cpu: impl Fn() -> CPU<FlatRam>, instructions: usize) -> String {
+    let mut cpu = make_cpu();
+    let mut lines = VecDeque::with_capacity(TRACE_LINES);
+    for i in 0..=instructions {
+        if i + TRACE_LINES > instructions {
+            if lines.len() == TRACE_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(trace(&cpu));
+        }
+        if cpu.step().is_err() {
+            break;
+        }
+    }
+    lines.into_iter().collect::<Vec<_>>().join("\n")
+}
+
+fn check_trap(make_cpu: impl Fn() -> CPU<FlatRam>, passed: impl Fn(&CPU<FlatRam>, u16) -> bool) {
+    let mut cpu = make_cpu();
+    let Some((trap, instructions)) = run_until_trap(&mut cpu, MAX_INSTRUCTIONS) else {
+        panic!("no trap after {} instructions", MAX_INSTRUCTIONS);
+    };
+
+    if !passed(&cpu, trap) {
+        panic!(
+            "trapped at {:04x} after {} instructions, {} cycles. last {} instructions:\n{}",
+            trap,
+            instructions,
+            cpu.cycles,
+            TRACE_LINES,
+            trace_tail(make_cpu, instructions)
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs the test binary, set KLAUS_FUNCTIONAL_TEST"]
+fn functional_test() {
+    let path = env::var("KLAUS_FUNCTIONAL_TEST").expect("KLAUS_FUNCTIONAL_TEST not set");
+    let image = fs::read(&path).unwrap();
+    let success = env::var("KLAUS_SUCCESS_ADDR")
+        .map(|addr| u16::from_str_radix(addr.trim_start_matches("0x"), 16).unwrap())
+        .unwrap_or(FUNCTIONAL_SUCCESS);
+
+    check_trap(
+        || load(&image, 0x0000, FUNCTIONAL_ENTRY),
+        |_, trap| trap == success,
+    );
+}
+
+#[test]
+#[ignore = "needs the test binary, set KLAUS_DECIMAL_TEST"]
+fn decimal_test() {
+    let path = env::var("KLAUS_DECIMAL_TEST").expect("KLAUS_DECIMAL_TEST not set");
+    let image = fs::read(&path).unwrap();
+
+    check_trap(
+        || load(&image, DECIMAL_LOAD, DECIMAL_LOAD),
+        |cpu, _| cpu.mem_peek(DECIMAL_ERROR) == 0,
+    );
+}
+
+#[test]
+fn detects_traps() {
+    // LDX #$03 ; loop: DEX ; BNE loop ; done: JMP done
+    let program = [0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x4c, 0x05, 0x04];
+    let mut cpu = load(&program, 0x0400, 0x0400);
+
+    assert_eq!(run_until_trap(&mut cpu, 100), Some((0x0405, 7)));
+    assert_eq!(cpu.register_x, 0);
+}
+
+#[test]
+fn failure_trace_ends_at_the_trap() {
+    let program = [0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x4c, 0x05, 0x04];
+    let tail = trace_tail(|| load(&program, 0x0400, 0x0400), 7);
+
+    let lines: Vec<&str> = tail.lines().collect();
+    assert_eq!(lines.len(), 8);
+    assert!(lines[0].starts_with("0400  A2 03"), "{}", tail);
+    assert!(
+        lines[7].starts_with("0405  4C 05 04  JMP $0405"),
+        "{}",
+        tail
+    );
+}
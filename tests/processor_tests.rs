@@ -0,0 +1,290 @@
+// runs the single step test vectors from https://github.com/SingleStepTests/65x02
+// (Tom Harte's ProcessorTests format). the vectors are too big to keep in the repo, so point
+// PROCESSOR_TESTS_DIR at a local directory with the per-opcode json files, e.g.
+//
+//   PROCESSOR_TESTS_DIR=~/65x02/nes6502/v1 cargo test --test processor_tests -- --ignored --nocapture
+//
+// the variant is guessed from the directory name (nes6502, 6502 or wdc65c02), set
+// PROCESSOR_TESTS_VARIANT to one of those to override it. PROCESSOR_TESTS_OPCODES takes a
+// comma separated list of hex opcodes to only run some of the files
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use rustendo::{
+    cpu::CPU,
+    flags::Flags,
+    mem::Mem,
+    opcodes,
+    test_bus::{Access, TestBus},
+    unstable_ops::UnstableOps,
+    variant::Variant,
+};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+// the vectors spell out accesses as (addr, data, "read" or "write")
+fn access(&(addr, data, ref kind): &(u16, u8, String)) -> Access {
+    match kind.as_str() {
+        "read" => Access::Read(addr, data),
+        "write" => Access::Write(addr, data),
+        _ => panic!("unknown access kind {}", kind),
+    }
+}
+
+// bits 4 and 5 only exist on the stack, the vectors don't agree on what they read back as
+const UNUSED_FLAGS: u8 = 0b0011_0000;
+
+// runs one vector and describes every mismatch, an empty string means it passed
+fn run_case(variant: Variant, case: &TestCase) -> String {
+    let mut bus = TestBus::new();
+    for &(addr, data) in &case.initial.ram {
+        bus.ram.mem_write(addr, data);
+    }
+
+    let mut cpu = CPU::new(bus);
+    cpu.variant = variant;
+    // the vectors were generated from visual6502, magic constants and all
+    cpu.unstable_ops = UnstableOps::VISUAL6502;
+    cpu.program_counter = case.initial.pc;
+    cpu.stack_pointer = case.initial.s;
+    cpu.register_a = case.initial.a;
+    cpu.register_x = case.initial.x;
+    cpu.register_y = case.initial.y;
+    cpu.status = Flags::from_bits_truncate(case.initial.p);
+
+    let mut diff = String::new();
+    let result = match cpu.step() {
+        Ok(result) => result,
+        Err(err) => return format!("{}: {}\n", case.name, err),
+    };
+
+    let expected = &case.expected;
+    let registers = [
+        ("pc", cpu.program_counter, expected.pc),
+        ("s", cpu.stack_pointer as u16, expected.s as u16),
+        ("a", cpu.register_a as u16, expected.a as u16),
+        ("x", cpu.register_x as u16, expected.x as u16),
+        ("y", cpu.register_y as u16, expected.y as u16),
+    ];
+    for (name, actual, expected) in registers {
+        if actual != expected {
+            writeln!(
+                diff,
+                "  {}: {:04x}, expected {:04x}",
+                name, actual, expected
+            )
+            .unwrap();
+        }
+    }
+
+    let status = cpu.status.bits() | UNUSED_FLAGS;
+    if status != expected.p | UNUSED_FLAGS {
+        writeln!(
+            diff,
+            "  p: {:08b}, expected {:08b} (NV--DIZC)",
+            status,
+            expected.p | UNUSED_FLAGS
+        )
+        .unwrap();
+    }
+
+    for &(addr, data) in &expected.ram {
+        let actual = cpu.bus().ram.mem_peek(addr);
+        if actual != data {
+            writeln!(
+                diff,
+                "  ram[{:04x}]: {:02x}, expected {:02x}",
+                addr, actual, data
+            )
+            .unwrap();
+        }
+    }
+
+    if result.cycles != case.cycles.len() {
+        writeln!(
+            diff,
+            "  took {} cycles, expected {}",
+            result.cycles,
+            case.cycles.len()
+        )
+        .unwrap();
+    }
+
+    let log = &cpu.bus().log;
+    let expected_log: Vec<Access> = case.cycles.iter().map(access).collect();
+    if *log != expected_log {
+        writeln!(diff, "  bus accesses:").unwrap();
+        for i in 0..log.len().max(expected_log.len()) {
+            let show = |access: Option<&Access>| match access {
+                Some(Access::Read(addr, data)) => format!("{:04x} {:02x} read ", addr, data),
+                Some(Access::Write(addr, data)) => format!("{:04x} {:02x} write", addr, data),
+                None => format!("{:13}", "-"),
+            };
+            let actual = log.get(i);
+            let expected = expected_log.get(i);
+            let marker = if actual == expected { " " } else { "!" };
+            writeln!(
+                diff,
+                "   {} {}  expected {}",
+                marker,
+                show(actual),
+                show(expected)
+            )
+            .unwrap();
+        }
+    }
+
+    if diff.is_empty() {
+        diff
+    } else {
+        format!("{}:\n{}", case.name, diff)
+    }
+}
+
+// runs every vector in a file, returns how many failed and the first few diffs
+fn run_file(variant: Variant, path: &Path) -> (usize, usize, String) {
+    let json = fs::read_to_string(path).unwrap();
+    let cases: Vec<TestCase> = serde_json::from_str(&json)
+        .unwrap_or_else(|err| panic!("couldn't parse {}: {}", path.display(), err));
+
+    let mut failed = 0;
+    let mut report = String::new();
+    for case in &cases {
+        // one instruction blowing up shouldn't hide how the rest of the file did
+        let diff = panic::catch_unwind(AssertUnwindSafe(|| run_case(variant, case)))
+            .unwrap_or_else(|_| format!("{}: panicked\n", case.name));
+        if !diff.is_empty() {
+            failed += 1;
+            if failed <= 3 {
+                report.push_str(&diff);
+            }
+        }
+    }
+    (cases.len(), failed, report)
+}
+
+fn variant_for(dir: &Path) -> Variant {
+    let name = env::var("PROCESSOR_TESTS_VARIANT")
+        .unwrap_or_else(|_| dir.to_string_lossy().to_lowercase());
+    if name.contains("nes6502") {
+        Variant::Ricoh2A03
+    } else if name.contains("65c02") {
+        Variant::Wdc65C02
+    } else {
+        Variant::Nmos6502
+    }
+}
+
+#[test]
+#[ignore = "needs the test vectors, set PROCESSOR_TESTS_DIR"]
+fn processor_tests() {
+    let dir = env::var("PROCESSOR_TESTS_DIR").expect("PROCESSOR_TESTS_DIR not set");
+    let dir = Path::new(&dir);
+    let variant = variant_for(dir);
+
+    let only: Option<Vec<u8>> = env::var("PROCESSOR_TESTS_OPCODES").ok().map(|list| {
+        list.split(',')
+            .map(|code| u8::from_str_radix(code.trim(), 16).unwrap())
+            .collect()
+    });
+
+    let mut total_failed = 0;
+    let mut files = 0;
+    for code in 0..=0xffu8 {
+        if only.as_ref().is_some_and(|only| !only.contains(&code)) {
+            continue;
+        }
+        // a jammed CPU keeps the bus busy forever, the vectors model that in ways we don't
+        let opcode = opcodes::lookup(variant, code);
+        if opcode.name == "*JAM" || opcode.name == "STP" || opcode.name == "WAI" {
+            continue;
+        }
+
+        let path = dir.join(format!("{:02x}.json", code));
+        if !path.exists() {
+            continue;
+        }
+
+        files += 1;
+        let (cases, failed, report) = run_file(variant, &path);
+        if failed > 0 {
+            total_failed += failed;
+            println!(
+                "{:02x} {}: {} of {} failed\n{}",
+                code, opcode.name, failed, cases, report
+            );
+        }
+    }
+
+    assert!(files > 0, "no test files found in {}", dir.display());
+    assert_eq!(total_failed, 0, "{} vectors failed", total_failed);
+}
+
+#[test]
+fn sample_vector_passes() {
+    // LDA ($10),Y crossing into the next page
+    let json = r#"{
+        "name": "b1 10 00",
+        "initial": {
+            "pc": 512, "s": 253, "a": 0, "x": 0, "y": 32, "p": 36,
+            "ram": [[512, 177], [513, 16], [16, 240], [17, 2], [528, 85], [784, 153]]
+        },
+        "final": {
+            "pc": 514, "s": 253, "a": 153, "x": 0, "y": 32, "p": 164,
+            "ram": [[512, 177], [513, 16], [16, 240], [17, 2], [528, 85], [784, 153]]
+        },
+        "cycles": [
+            [512, 177, "read"], [513, 16, "read"], [16, 240, "read"],
+            [17, 2, "read"], [528, 85, "read"], [784, 153, "read"]
+        ]
+    }"#;
+
+    let case: TestCase = serde_json::from_str(json).unwrap();
+    assert_eq!(run_case(Variant::Nmos6502, &case), "");
+}
+
+#[test]
+fn mismatches_are_reported() {
+    // INC $10, but the vector claims a single write
+    let json = r#"{
+        "name": "e6 10 00",
+        "initial": {
+            "pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+            "ram": [[512, 230], [513, 16], [16, 1]]
+        },
+        "final": {
+            "pc": 514, "s": 253, "a": 0, "x": 0, "y": 0, "p": 36,
+            "ram": [[512, 230], [513, 16], [16, 2]]
+        },
+        "cycles": [
+            [512, 230, "read"], [513, 16, "read"], [16, 1, "read"], [16, 2, "write"]
+        ]
+    }"#;
+
+    let case: TestCase = serde_json::from_str(json).unwrap();
+    let diff = run_case(Variant::Nmos6502, &case);
+    assert!(diff.contains("took 5 cycles, expected 4"), "{}", diff);
+    assert!(diff.contains("! 0010 01 write"), "{}", diff);
+}